@@ -3,24 +3,122 @@
 //! COSMIC Ollama Applet - A panel applet for chatting with local AI models.
 //!
 //! This applet provides quick access to Ollama-powered AI assistance from
-//! the COSMIC desktop panel. It automatically gathers system context like
-//! clipboard content, selected text, and recent errors to provide relevant help.
-
-use crate::config::Config;
-use crate::context::Context;
-use crate::history;
-use crate::ollama::{self, AvailableModel, Client as OllamaClient, StreamEvent};
-use cosmic::cosmic_config::{self, CosmicConfigEntry};
-use cosmic::iced::{Alignment, Length, Limits, Subscription, window::Id};
+//! the COSMIC desktop panel. It can add system details and recent errors to
+//! a conversation, and the clipboard or selected text once they're attached.
+
+use crate::config::{self, Config, RevealMode};
+use crate::context::{self, Attachment, Context, ImageAttachment};
+use crate::dbus::{self, AskRequest};
+use crate::highlight::{self, TokenKind};
+use crate::history::{self, ChatHistory, Conversation, HistoryMessage};
+use crate::http;
+use crate::markdown::{self, Block, Inline};
+use crate::ollama::{
+    self, ApiFormat, AvailableModel, CancelToken, ChatOptions, Client as OllamaClient,
+    GenerationStats, KeepAlive, ModelShow, PullEvent, ServerInfo, StreamEvent, TokenUsage,
+};
+use crate::web::{self, SearchBackend, SearchMode};
+use cosmic::cosmic_config::{self, ConfigGet, ConfigSet, CosmicConfigEntry};
+use cosmic::dialog::file_chooser;
+use cosmic::iced::widget::text::{Span, Wrapping};
+use cosmic::iced::widget::{progress_bar, rich_text, scrollable, span, text_editor};
+use cosmic::iced::{
+    Alignment, Color, Font, Length, Limits, Subscription, event, font, keyboard, time, window::Id,
+};
 use cosmic::iced_winit::commands::popup::{destroy_popup, get_popup};
 use cosmic::prelude::*;
 use cosmic::{theme, widget};
-use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use futures_util::stream;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio::task::AbortHandle;
 
 /// Application identifier for COSMIC/freedesktop.
 pub const APP_ID: &str = "com.github.paulwade.cosmic-applet-ollama";
 
+/// Number of submitted prompts remembered for Up/Down recall.
+const PROMPT_HISTORY_SIZE: usize = 50;
+
+/// View heights of messages built past the visible part of the chat when
+/// its window of built messages grows.
+const CHAT_BUFFER_VIEWS: f32 = 2.0;
+
+/// Built messages further than this many view heights out of sight are
+/// dropped again.
+const CHAT_KEEP_VIEWS: f32 = 4.0;
+
+/// Size of the chat view assumed until it's first laid out.
+const CHAT_FALLBACK_WIDTH: f32 = 400.0;
+const CHAT_FALLBACK_HEIGHT: f32 = 600.0;
+
+/// Panel icon while a response is being generated.
+const GENERATING_ICON: &str = "content-loading-symbolic";
+
+/// Panel icon when a response finished while the popup was closed.
+const UNREAD_ICON: &str = "mail-unread-symbolic";
+
+/// Characters of captured clipboard or selection text shown in its chip.
+const PREVIEW_CHARS: usize = 40;
+
+/// Shares of the chat width taken by a bubble and the gap beside it when
+/// bubbles are aligned to the sides.
+const BUBBLE_PORTION: u16 = 5;
+const BUBBLE_GAP_PORTION: u16 = 1;
+
+/// Height the message input grows to before it scrolls.
+const INPUT_MAX_HEIGHT: f32 = 160.0;
+
+/// Largest side of an image thumbnail in a chat bubble.
+const THUMBNAIL_SIZE: f32 = 120.0;
+
+/// Id of the chat scrollable, for pinning it to the newest message.
+static CHAT_SCROLL_ID: LazyLock<widget::Id> = LazyLock::new(|| widget::Id::new("chat-scroll"));
+
+/// Id of the conversation search field, for focusing it when opened.
+static SEARCH_INPUT_ID: LazyLock<widget::Id> =
+    LazyLock::new(|| widget::Id::new("conversation-search"));
+
+/// Settings dropdown entries, in `ApiFormat::ALL` order.
+const API_FORMAT_LABELS: [&str; 2] = [ApiFormat::Ollama.label(), ApiFormat::OpenAI.label()];
+
+/// Keep-alive presets offered in the settings, and the values they store.
+const KEEP_ALIVE_LABELS: [&str; 3] = ["Unload immediately", "5 minutes", "Keep loaded"];
+const KEEP_ALIVE_VALUES: [&str; 3] = ["0", config::DEFAULT_KEEP_ALIVE, "-1"];
+
+/// Context window presets offered in the settings, and the sizes they store.
+const NUM_CTX_LABELS: [&str; 7] = ["Model default", "4K", "8K", "16K", "32K", "64K", "128K"];
+const NUM_CTX_VALUES: [Option<u32>; 7] = [
+    None,
+    Some(4096),
+    Some(8192),
+    Some(16_384),
+    Some(32_768),
+    Some(65_536),
+    Some(131_072),
+];
+
+/// Settings dropdown entries, in `SearchMode::ALL` order.
+const SEARCH_MODE_LABELS: [&str; 3] = [
+    SearchMode::Heuristic.label(),
+    SearchMode::Always.label(),
+    SearchMode::Never.label(),
+];
+
+/// Settings dropdown entries, in `RevealMode::ALL` order.
+const REVEAL_MODE_LABELS: [&str; 3] = [
+    RevealMode::Stream.label(),
+    RevealMode::Instant.label(),
+    RevealMode::Typewriter.label(),
+];
+
+/// Greeting shown in a conversation without messages.
+const WELCOME_MESSAGE: &str =
+    "Hi! I'm your local AI assistant. Attach copied text for context, then ask me anything.";
+
 /// The main application state.
 #[derive(Default)]
 pub struct AppModel {
@@ -32,12 +130,66 @@ pub struct AppModel {
     config: Config,
     /// Config context for saving changes.
     config_ctx: Option<cosmic_config::Config>,
+    /// The conversation being shown.
+    conversation: Conversation,
+    /// All conversations, most recently updated first. Includes the active
+    /// one even before it's first saved.
+    conversations: Vec<Conversation>,
+    /// Conversation titles for the picker (cached).
+    conversation_titles: Vec<String>,
+    /// Persona names for the picker, after an entry for the default prompt.
+    persona_options: Vec<String>,
     /// Current text input value.
     input_text: String,
-    /// Chat message history as (role, content) pairs.
-    messages: Vec<(String, String)>,
+    /// Editor state of the message input, kept in sync with `input_text`.
+    input_editor: text_editor::Content,
+    /// Recently submitted prompts, oldest first, for Up/Down recall.
+    prompt_history: Vec<String>,
+    /// Position in `prompt_history` while recalling, `None` when editing.
+    prompt_history_index: Option<usize>,
+    /// Chat message history.
+    messages: Vec<HistoryMessage>,
+    /// Last reported position of the chat view.
+    chat_viewport: Option<scrollable::Viewport>,
+    /// Messages built into the chat view while it's scrolled up; `None`
+    /// builds the newest ones.
+    chat_window: Option<Range<usize>>,
+    /// Content height of the chat view just before the top of
+    /// `chat_window` moved, until the view is scrolled to make up for it.
+    chat_anchor: Option<f32>,
+    /// The user scrolled away from the bottom, so new content shouldn't
+    /// pull the view down.
+    chat_scrolled_up: bool,
     /// Whether we're waiting for an AI response.
     waiting: bool,
+    /// A response finished while the popup was closed and hasn't been seen.
+    unread: bool,
+    /// Messages submitted while waiting, sent together once the stream ends.
+    queued: Vec<String>,
+    /// Attach static context on the next turn even if it isn't the first.
+    refresh_static_context: bool,
+    /// Web search override for the next message only.
+    search_mode: Option<SearchMode>,
+    /// File to attach to the next message.
+    attachment: Option<Attachment>,
+    /// Image to send with the next message.
+    image: Option<ImageAttachment>,
+    /// Clipboard contents captured for the next message.
+    attached_clipboard: Option<String>,
+    /// Primary selection captured for the next message.
+    attached_selection: Option<String>,
+    /// Decoded message images, keyed by their stored name.
+    thumbnails: HashMap<String, widget::image::Handle>,
+    /// The most recent request, kept so it can be retried.
+    last_request: Option<TurnRequest>,
+    /// Context sources attached to the most recent request.
+    attached_sources: Option<Vec<&'static str>>,
+    /// Incremented per turn so results of a stopped turn are ignored.
+    turn: u64,
+    /// Cancels the in-flight turn's context gathering and web search.
+    cancel: Option<watch::Sender<bool>>,
+    /// Aborts the task streaming the in-flight response.
+    stream_task: Option<AbortHandle>,
     /// Receiver for streaming response chunks (wrapped for Clone).
     stream_rx: Option<Arc<Mutex<mpsc::Receiver<StreamEvent>>>>,
     /// Available models from Ollama.
@@ -46,6 +198,209 @@ pub struct AppModel {
     model_options: Vec<String>,
     /// Whether we're loading models.
     loading_models: bool,
+    /// Details from /api/show, cached per model name.
+    model_details: HashMap<String, ModelShow>,
+    /// Tokens used by the current conversation.
+    token_usage: TokenUsage,
+    /// Text most recently copied from a code block, while its button
+    /// shows the copied state.
+    copied: Option<String>,
+    /// Whether the settings view replaces the chat.
+    show_settings: bool,
+    /// Whether the active model's details replace the chat.
+    show_model_info: bool,
+    /// Whether the conversation search field is shown.
+    show_search: bool,
+    /// Text to find in the conversation; only matching messages are shown.
+    search_query: String,
+    /// Whether the reasoning of reasoning models is expanded in the bubbles.
+    show_thoughts: bool,
+    /// Whether the API key is shown in plain text in the settings.
+    api_key_visible: bool,
+    /// SearXNG instance URL being edited in the settings.
+    searxng_url: String,
+    /// Server URL being edited in the settings.
+    url_input: String,
+    /// Default model being edited in the settings.
+    model_input: String,
+    /// Request timeout being edited in the settings.
+    timeout_input: String,
+    /// Stop sequence being typed in the settings.
+    stop_input: String,
+    /// Outcome of "Test connection" for the URL being edited: the number of
+    /// models found, or why it failed.
+    connection_test: Option<Result<usize, String>>,
+    /// Incremented per edit of the URL, model or timeout field so only the
+    /// last edit's delayed save goes through.
+    settings_edit_id: u64,
+    /// Whether the model management rows (pull, delete) are shown under
+    /// the model picker.
+    manage_models: bool,
+    /// Installed model awaiting delete confirmation.
+    confirm_delete: Option<String>,
+    /// Imported conversation waiting for the user to merge it or open it
+    /// separately.
+    pending_import: Option<ChatHistory>,
+    /// Whether the clear button was pressed once and waits for a second
+    /// press to actually clear the chat.
+    confirming_clear: bool,
+    /// Incremented per first clear press so stale timeouts are ignored.
+    confirm_clear_id: u64,
+    /// Name typed into the pull-a-model field.
+    pull_name: String,
+    /// Receiver for progress of the model being pulled.
+    pull_rx: Option<Arc<Mutex<mpsc::Receiver<PullEvent>>>>,
+    /// Bytes downloaded and total of the layer being pulled.
+    pull_progress: (u64, u64),
+    /// Model the last request failed for because it isn't installed,
+    /// offered for pulling from the error bubble.
+    missing_model: Option<String>,
+    /// Reachability of the server, from the last ping or model listing.
+    connection: ConnectionStatus,
+    /// Whether the offline banner was closed; it returns after the server
+    /// has been reachable again.
+    offline_dismissed: bool,
+    /// Whether the model chooser under the last response is open.
+    choosing_regenerate_model: bool,
+    /// Last failed request, cleared by the next successful one.
+    last_error: Option<LastError>,
+    /// Whether the details of `last_error` are shown.
+    show_last_error: bool,
+    /// Frame of the typing indicator shown until the first answer token.
+    typing_frame: usize,
+    /// When the current request was sent, for telling a model that is
+    /// still being loaded from one that's generating.
+    turn_started: Option<Instant>,
+    /// Editor state for the system prompt in the settings view.
+    prompt_editor: text_editor::Content,
+    /// Server version detected on connect; `None` until probed.
+    server: Option<ServerInfo>,
+    /// Transient status line shown under the header (e.g. config save result).
+    status: Option<Status>,
+    /// Incremented for each new status so stale clear timers are ignored.
+    status_id: u64,
+    /// Whether a save of the streaming answer is already scheduled.
+    autosave_pending: bool,
+    /// Answer text received but held back by the instant or typewriter
+    /// reveal mode.
+    pending_reveal: String,
+}
+
+/// Parameters for generating one assistant turn.
+#[derive(Debug, Clone)]
+struct TurnRequest {
+    /// The user message the response is for.
+    query: String,
+    /// Model to generate with.
+    model: String,
+    /// Whether to attach static system context.
+    include_static: bool,
+    /// Web search behavior for this turn.
+    search_mode: SearchMode,
+    /// File attached to this turn.
+    attachment: Option<Attachment>,
+    /// Clipboard contents attached to this turn.
+    clipboard: Option<String>,
+    /// Primary selection attached to this turn.
+    selection: Option<String>,
+    /// Base64 images sent with this turn only.
+    images: Vec<String>,
+}
+
+/// A context source that can be switched off in the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextSource {
+    Clipboard,
+    Selection,
+    SystemInfo,
+    RecentErrors,
+}
+
+/// Whether the server answered the last request made to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum ConnectionStatus {
+    /// Nothing has been sent yet.
+    #[default]
+    Unknown,
+    Connected,
+    /// Why the last attempt failed.
+    Disconnected(String),
+}
+
+/// How often the server is pinged while the popup is open.
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Size of chat text at a font scale of 1.
+const BASE_TEXT_SIZE: f32 = 14.0;
+
+/// Size of small print, like error reports, at a font scale of 1.
+const BASE_CAPTION_SIZE: f32 = 12.0;
+
+/// Change in font scale per press of the text size buttons.
+const FONT_SCALE_STEP: f32 = 0.125;
+
+/// How often the dots of the typing indicator advance.
+const TYPING_FRAME_INTERVAL: Duration = Duration::from_millis(400);
+
+/// How long a request can go without any output before the server is
+/// assumed to be loading the model into memory.
+const MODEL_LOAD_HINT_DELAY: Duration = Duration::from_secs(4);
+
+/// How often the typewriter reveals more of the answer.
+const REVEAL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Ticks the typewriter spreads its backlog over, so it speeds up rather
+/// than falling far behind a fast model.
+const REVEAL_CATCH_UP_TICKS: usize = 25;
+
+/// How long a first press of the clear button waits for the second.
+const CLEAR_CONFIRM_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How long typing in a settings field pauses before the value is saved.
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_millis(800);
+
+/// A short-lived status message shown in the popup.
+#[derive(Debug, Clone)]
+struct Status {
+    text: String,
+    is_error: bool,
+}
+
+/// The most recent failed request, kept for troubleshooting after its
+/// bubble has scrolled away.
+#[derive(Debug, Clone)]
+struct LastError {
+    /// What failed, e.g. "Chat request".
+    action: &'static str,
+    /// Server URL the request went to.
+    url: String,
+    /// Server version, when the probe got one.
+    version: Option<String>,
+    model: String,
+    /// The error as reported by the client, with HTTP status or
+    /// connection details.
+    detail: String,
+    /// Unix timestamp (seconds) of the failure.
+    time: u64,
+}
+
+impl LastError {
+    /// Everything known about the failure, for reading or pasting into a
+    /// bug report.
+    fn report(&self) -> String {
+        let server = match &self.version {
+            Some(version) => format!("{} (Ollama {})", self.url, version),
+            None => self.url.clone(),
+        };
+        format!(
+            "{} failed at {}\nServer: {}\nModel: {}\nError: {}",
+            self.action,
+            history::format_timestamp(self.time),
+            server,
+            self.model,
+            self.detail
+        )
+    }
 }
 
 /// Application messages for state updates.
@@ -53,47 +408,892 @@ pub struct AppModel {
 pub enum Message {
     /// Toggle the popup window visibility.
     TogglePopup,
+    /// Close the popup (Escape).
+    ClosePopup,
     /// Handle popup window close.
     PopupClosed(Id),
     /// Configuration update from cosmic-config.
     UpdateConfig(Config),
-    /// Text input changed.
-    InputChanged(String),
+    /// Typing or cursor movement in the message input.
+    EditInput(text_editor::Action),
+    /// Choose whether Enter sends or starts a new line.
+    SetEnterSends(bool),
+    /// Turn the accent style for your messages on or off.
+    SetUserBubbleAccent(bool),
+    /// Turn side-aligned bubbles on or off.
+    SetAlignBubbles(bool),
+    /// Choose whether long code lines wrap or scroll.
+    SetWrapCode(bool),
+    /// Pick how answers appear by index in `RevealMode::ALL`.
+    SetRevealMode(usize),
+    /// Time for the typewriter to show the next characters.
+    RevealTick,
     /// User submitted a message.
     Submit,
-    /// Stream is ready, start receiving chunks.
-    StreamReady(Arc<Mutex<mpsc::Receiver<StreamEvent>>>),
+    /// Stream is ready, start receiving chunks. Carries the turn it belongs
+    /// to, the streaming task, the names of the context sources that
+    /// were attached and why the web search failed, if it did.
+    StreamReady(
+        u64,
+        Arc<Mutex<mpsc::Receiver<StreamEvent>>>,
+        AbortHandle,
+        Vec<&'static str>,
+        Option<String>,
+    ),
+    /// The whole response of a non-streaming turn arrived, with the same
+    /// turn, sources and search error `StreamReady` carries.
+    ResponseComplete {
+        turn: u64,
+        response: Result<(String, TokenUsage), String>,
+        sources: Vec<&'static str>,
+        search_error: Option<String>,
+    },
     /// Received a streaming chunk from Ollama.
     StreamChunk(String),
+    /// Time to save the answer streaming in the given turn.
+    AutosaveStream(u64),
+    /// Generation stats for the response: tokens and tokens per second.
+    StreamStats(u32, f32),
     /// Stream completed.
-    StreamDone,
+    StreamDone(TokenUsage),
     /// Stream error occurred.
     StreamError(String),
-    /// Poll for next stream chunk.
+    /// The model used for the stream isn't installed.
+    ModelNotFound(String),
+    /// The stream's channel closed without a final event.
+    StreamClosed,
+    /// Check the stream for chunks, with `stream_polling` on.
     PollStream,
-    /// Clear chat history.
+    /// Clear chat history; the first press only asks for confirmation.
     ClearChat,
+    /// Drop the clear confirmation if it is still the one with this id.
+    CancelClear(u64),
+    /// Start a new conversation.
+    NewConversation,
+    /// Replace the last assistant response with a new one.
+    Regenerate,
+    /// Show or hide the model chooser for regenerating the last response.
+    ToggleRegenerateModels,
+    /// Replace the last assistant response with one from this model, for
+    /// that request only.
+    RegenerateWith(usize),
+    /// Put a user message back in the input, dropping it and everything
+    /// after it, so it can be edited and resent.
+    EditMessage(usize),
+    /// Switch to the conversation with this id.
+    SwitchConversation(u64),
     /// Load available models from Ollama.
     LoadModels,
     /// Received available models from Ollama.
     ModelsLoaded(Result<Vec<AvailableModel>, String>),
     /// User selected a different model.
     SelectModel(usize),
+    /// User picked a persona for the conversation; 0 is the default prompt.
+    SelectPersona(usize),
+    /// Another program asked a question over D-Bus.
+    ExternalAsk(AskRequest),
+    /// Received the server version from /api/version.
+    ServerProbed(Result<ServerInfo, String>),
+    /// Received details for a model from /api/show.
+    ModelDetailsLoaded(String, Result<ModelShow, String>),
+    /// Show or hide the active model's details.
+    ToggleModelInfo,
+    /// Clear the status line if it is still the one with this id.
+    ClearStatus(u64),
+    /// Remove a queued message before it is sent.
+    RemoveQueued(usize),
+    /// Re-attach static system context on the next message.
+    RefreshContext,
+    /// Cycle the web search override for the next message.
+    CycleSearchMode,
+    /// Recall the previous submitted prompt into the input.
+    RecallPrevious,
+    /// Recall the next submitted prompt (or clear past the newest).
+    RecallNext,
+    /// The chat view was scrolled.
+    ChatScrolled(scrollable::Viewport),
+    /// Open a file picker to attach a text file.
+    AttachFile,
+    /// A file was read for attaching (or failed to).
+    FileAttached(Result<Attachment, String>),
+    /// Drop the pending attachment.
+    RemoveAttachment,
+    /// Capture the clipboard or selection for the next message.
+    AttachText(ContextSource),
+    /// The capture finished (or failed).
+    TextAttached(ContextSource, Result<String, String>),
+    /// Drop a captured clipboard or selection.
+    RemoveText(ContextSource),
+    /// Open a file picker to attach an image.
+    AttachImage,
+    /// An image was read for attaching (or failed to).
+    ImageAttached(Result<ImageAttachment, String>),
+    /// Drop the pending image.
+    RemoveImage,
+    /// Toggle concise answers.
+    ToggleConcise,
+    /// Show or hide the details of the last error.
+    ToggleLastError,
+    /// Forget the last error.
+    DismissLastError,
+    /// Copy text (e.g. a code block) to the clipboard.
+    CopyText(String),
+    /// A copy finished (or failed).
+    Copied(String, Result<(), String>),
+    /// Revert a copy button's icon if it still shows this text.
+    ClearCopied(String),
+    /// Show or hide the settings view.
+    ToggleSettings,
+    /// Show or hide the conversation search field.
+    ToggleSearch,
+    /// The conversation search text changed.
+    SearchChanged(String),
+    /// Expand or collapse reasoning sections.
+    ToggleThoughts,
+    /// Keep or drop reasoning from finished answers.
+    SetStripReasoning(bool),
+    /// Change the chat text scale by this many steps.
+    AdjustFontScale(i8),
+    /// Popup width slider moved.
+    SetPopupWidth(f32),
+    /// Popup height slider moved.
+    SetPopupHeight(f32),
+    /// Temperature slider moved.
+    SetTemperature(f32),
+    /// Top-p slider moved.
+    SetTopP(f32),
+    /// Pick a context window preset by its index in `NUM_CTX_VALUES`.
+    SetNumCtx(usize),
+    /// Edit in the stop sequence field.
+    StopInputChanged(String),
+    /// Add the typed stop sequence.
+    AddStopSequence,
+    /// Remove a stop sequence by its index.
+    RemoveStopSequence(usize),
+    /// A slider was released; persist the settings.
+    SaveSettings,
+    /// Turn a context source on or off.
+    SetContextSource(ContextSource, bool),
+    /// Pick the server's API format by its index in `ApiFormat::ALL`.
+    SetApiFormat(usize),
+    /// Ping the server to refresh the connection indicator.
+    CheckConnection,
+    /// Advance the typing indicator.
+    TypingTick,
+    /// A ping finished.
+    ConnectionChecked(Result<(), String>),
+    /// Retry reaching the server from the offline banner.
+    RetryConnection,
+    /// Close the offline banner.
+    DismissOffline,
+    /// Show or hide the model management rows.
+    ToggleManageModels,
+    /// Ask to delete the installed model at this index.
+    DeleteModel(usize),
+    /// Delete the model awaiting confirmation.
+    ConfirmDelete,
+    /// Keep the model awaiting confirmation.
+    CancelDelete,
+    /// A model was deleted (or failed to be).
+    ModelDeleted(String, Result<(), String>),
+    /// Edit in the pull-a-model field.
+    PullNameChanged(String),
+    /// Start downloading the named model.
+    PullModel,
+    /// Pull the model the last request was missing.
+    PullMissingModel,
+    /// The pull request was sent; progress arrives on this receiver.
+    PullStarted(Arc<Mutex<mpsc::Receiver<PullEvent>>>),
+    /// Download progress of the current layer.
+    PullProgress { completed: u64, total: u64 },
+    /// The pull finished (or failed).
+    PullFinished(Result<(), String>),
+    /// Turn streaming on or off.
+    SetStreaming(bool),
+    /// Switch between chatting and raw completion.
+    SetRawCompletion(bool),
+    /// Pick a keep-alive preset by its index in `KEEP_ALIVE_VALUES`.
+    SetKeepAlive(usize),
+    /// Pick the default search mode by its index in `SearchMode::ALL`.
+    SetSearchMode(usize),
+    /// Pick a web search backend by its index in `SearchBackend::LABELS`.
+    SetSearchBackend(usize),
+    /// Edit in the SearXNG URL field.
+    SearxngUrlChanged(String),
+    /// Store the edited SearXNG URL.
+    SaveSearxngUrl,
+    /// Edit in the server URL field.
+    UrlChanged(String),
+    /// Store the edited server URL and reconnect.
+    SaveUrl,
+    /// List models at the URL being edited without saving it.
+    TestConnection,
+    /// The connection test of the given URL finished.
+    ConnectionTested(String, Result<Vec<AvailableModel>, String>),
+    /// Edit in the default model field.
+    ModelInputChanged(String),
+    /// Store the edited default model.
+    SaveModel,
+    /// Edit in the timeout field.
+    TimeoutChanged(String),
+    /// Store the edited timeout.
+    SaveTimeout,
+    /// Typing in the URL, model or timeout field paused; save what changed
+    /// if this is still the latest edit.
+    SaveEditedSettings(u64),
+    /// Edit in the API key field.
+    ApiKeyChanged(String),
+    /// Show or hide the API key.
+    ToggleApiKeyVisible,
+    /// Store the edited API key and reconnect with it.
+    SaveApiKey,
+    /// Edit in the system prompt editor.
+    EditSystemPrompt(text_editor::Action),
+    /// Store the edited system prompt.
+    SaveSystemPrompt,
+    /// Restore the built-in system prompt.
+    ResetSystemPrompt,
+    /// Stop button pressed: cancel the in-flight turn's web search and
+    /// stream, and drop queued follow-ups.
+    CancelStream,
+    /// Open a file picker to import a conversation.
+    ImportConversation,
+    /// A conversation file was read (or failed to).
+    ConversationImported(Result<ChatHistory, String>),
+    /// Load the pending import, appended to the current conversation if
+    /// true or as a conversation of its own otherwise.
+    ApplyImport(bool),
+    /// Drop the pending import.
+    CancelImport,
+    /// Save the conversation as a Markdown file.
+    ExportConversation,
+    /// The export was written (or failed to be); `None` if the dialog was
+    /// cancelled.
+    ConversationExported(Result<Option<PathBuf>, String>),
+}
+
+/// A turn's client and payload, with the system context attached.
+struct PreparedTurn {
+    client: OllamaClient,
+    system_prompt: String,
+    messages: Vec<ollama::Message>,
+    sources: Vec<&'static str>,
+    search_error: Option<String>,
+}
+
+/// Gather the system context for a turn and build its request.
+///
+/// Returns `None` if cancelled while gathering.
+async fn prepare_turn(
+    config: Config,
+    messages: Vec<ollama::Message>,
+    request: TurnRequest,
+    think: Option<bool>,
+    cancel: &mut CancelToken,
+) -> Option<PreparedTurn> {
+    // Gather context with web search if query suggests it (or if forced)
+    let gather = Context::gather_with_search(
+        &request.query,
+        &config,
+        request.include_static,
+        request.search_mode,
+    );
+    let mut context = tokio::select! {
+        context = gather => context,
+        () = cancel.cancelled() => return None,
+    };
+    context.file = request.attachment;
+    context.clipboard = request.clipboard;
+    context.selection = request.selection;
+    if config.redact_secrets {
+        context.redact();
+    }
+    let system_prompt = if config.concise_mode {
+        context.format(&format!(
+            "{}\n\n{}",
+            config.system_prompt,
+            ollama::CONCISE_INSTRUCTION
+        ))
+    } else {
+        context.format(&config.system_prompt)
+    };
+    let messages = ollama::fit_to_budget(&system_prompt, messages, config.context_budget_chars);
+
+    Some(PreparedTurn {
+        client: turn_client(config, think, cancel.clone()),
+        system_prompt,
+        messages,
+        sources: context.sources(),
+        search_error: context.search_error,
+    })
+}
+
+/// A client with the configured server and generation settings, whose
+/// stream stops when the turn is cancelled.
+fn turn_client(config: Config, think: Option<bool>, cancel: CancelToken) -> OllamaClient {
+    let options = ChatOptions::from_config(&config);
+    OllamaClient::new(config.ollama_url, config.model)
+        .with_format(config.api_format)
+        .with_api_key(config.api_key)
+        .with_think(think)
+        .with_options(options)
+        .with_max_retries(config.max_retries)
+        .with_keep_alive(KeepAlive::parse(&config.keep_alive))
+        .with_timeout(Duration::from_secs(config.timeout_secs.max(1)))
+        .with_cancel(cancel)
+}
+
+/// First line of captured text, shortened for a chip above the input.
+fn preview(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > PREVIEW_CHARS || text.trim().lines().nth(1).is_some() {
+        let shortened: String = line.chars().take(PREVIEW_CHARS).collect();
+        format!("{}…", shortened.trim_end())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Rough height of `text` laid out `width` wide, wrapping at an average
+/// glyph width.
+fn text_height(text: &str, width: f32, text_size: f32) -> f32 {
+    let per_line = (width / (text_size * 0.55)).max(1.0);
+    let lines: f32 = text
+        .lines()
+        .map(|line| (line.chars().count() as f32 / per_line).ceil().max(1.0))
+        .sum();
+    lines * text_size * 1.4
+}
+
+/// Blank space standing in for messages that aren't built, if any.
+fn chat_spacer(heights: &[f32]) -> Option<widget::Space> {
+    let height: f32 = heights.iter().sum();
+    (height > 0.0).then(|| widget::Space::with_height(height))
+}
+
+/// The newest messages whose estimated heights add up to `span`.
+fn newest_window(heights: &[f32], span: f32) -> Range<usize> {
+    let mut start = heights.len();
+    let mut built = 0.0;
+    while start > 0 && built < span {
+        start -= 1;
+        built += heights[start];
+    }
+    start..heights.len()
+}
+
+/// Index of the message at `y` when laid out with `heights`.
+fn index_at(heights: &[f32], y: f32) -> usize {
+    let mut bottom = 0.0;
+    heights
+        .iter()
+        .position(|height| {
+            bottom += height;
+            bottom > y
+        })
+        .unwrap_or(heights.len().saturating_sub(1))
+}
+
+/// Where the window of built messages moves for a chat view scrolled to
+/// `top`, and whether its top edge moved, which shifts what's in view.
+///
+/// Messages outside the window are stood in for by space of their
+/// estimated `heights`. The window grows when the view comes within a
+/// screen of either edge and is trimmed again once it reaches far past.
+fn shift_chat_window(
+    window: &Range<usize>,
+    heights: &[f32],
+    top: f32,
+    view_height: f32,
+    content_height: f32,
+) -> Option<(Range<usize>, bool)> {
+    let buffer = CHAT_BUFFER_VIEWS * view_height;
+    let keep = CHAT_KEEP_VIEWS * view_height;
+    let (mut start, mut end) = (window.start, window.end);
+    let space_above: f32 = heights[..start].iter().sum();
+    let space_below: f32 = heights[end..].iter().sum();
+    let bottom = top + view_height;
+    let built_bottom = content_height - space_below;
+    // How far off the estimates are for the messages already built
+    let estimated: f32 = heights[start..end].iter().sum();
+    let scale = if estimated > 0.0 {
+        (built_bottom - space_above) / estimated
+    } else {
+        1.0
+    };
+
+    // Jumped into the space: build around the estimated position instead
+    if bottom < space_above || top > built_bottom {
+        let first = index_at(heights, top - buffer);
+        let last = index_at(heights, bottom + buffer);
+        return Some((first..(last + 1).min(heights.len()), false));
+    }
+
+    let built_above = top - space_above;
+    if built_above < view_height && start > 0 {
+        let mut added = 0.0;
+        while start > 0 && added < buffer {
+            start -= 1;
+            added += heights[start] * scale;
+        }
+        return Some((start..end, true));
+    }
+    if built_above > keep {
+        let mut excess = built_above - buffer;
+        while start < end && heights[start] * scale < excess {
+            excess -= heights[start] * scale;
+            start += 1;
+        }
+        if start != window.start {
+            return Some((start..end, true));
+        }
+    }
+
+    let built_below = built_bottom - bottom;
+    if built_below < view_height && end < heights.len() {
+        let mut added = 0.0;
+        while end < heights.len() && added < buffer {
+            added += heights[end] * scale;
+            end += 1;
+        }
+        return Some((start..end, false));
+    }
+    if built_below > keep {
+        let mut excess = built_below - buffer;
+        while end > start && heights[end - 1] * scale < excess {
+            excess -= heights[end - 1] * scale;
+            end -= 1;
+        }
+        if end != window.end {
+            return Some((start..end, false));
+        }
+    }
+    None
+}
+
+/// Case-insensitive substring match against an already lowercased query.
+fn matches_search(content: &str, query: &str) -> bool {
+    content.to_lowercase().contains(query)
+}
+
+/// An assistant message that isn't part of the conversation proper, so it
+/// carries no time.
+fn greeting(text: &str) -> HistoryMessage {
+    HistoryMessage {
+        role: "assistant".to_string(),
+        content: text.to_string(),
+        ..Default::default()
+    }
 }
 
 /// Start a streaming chat with Ollama including system context.
+///
+/// In raw completion mode the message alone is sent to the generate API,
+/// so no context is gathered. Returns `None` if cancelled before the
+/// stream started.
 async fn start_ollama_stream(
-    url: String,
-    model: String,
-    messages: Vec<(String, String)>,
-    query: String,
-) -> mpsc::Receiver<StreamEvent> {
-    // Gather context with web search if query suggests it
-    let context = Context::gather_with_search(&query).await;
-    let system_prompt = context.format(ollama::DEFAULT_SYSTEM_PROMPT);
-    OllamaClient::new(url, model)
-        .chat_stream(system_prompt, messages)
-        .await
+    config: Config,
+    messages: Vec<ollama::Message>,
+    request: TurnRequest,
+    think: Option<bool>,
+    mut cancel: CancelToken,
+) -> Option<(
+    mpsc::Receiver<StreamEvent>,
+    AbortHandle,
+    Vec<&'static str>,
+    Option<String>,
+)> {
+    if config.uses_generate() {
+        let (rx, task) = turn_client(config, think, cancel)
+            .generate_stream(request.query)
+            .await;
+        return Some((rx, task, Vec::new(), None));
+    }
+
+    let turn = prepare_turn(config, messages, request, think, &mut cancel).await?;
+    let (rx, task) = turn
+        .client
+        .chat_stream(turn.system_prompt, turn.messages)
+        .await;
+
+    Some((rx, task, turn.sources, turn.search_error))
+}
+
+/// Ask Ollama for the whole response at once, including system context.
+///
+/// Returns `None` if cancelled before the response arrived.
+async fn request_ollama_response(
+    config: Config,
+    messages: Vec<ollama::Message>,
+    request: TurnRequest,
+    think: Option<bool>,
+    mut cancel: CancelToken,
+) -> Option<(
+    Result<(String, TokenUsage), String>,
+    Vec<&'static str>,
+    Option<String>,
+)> {
+    let turn = prepare_turn(config, messages, request, think, &mut cancel).await?;
+    // Dropping the request on cancel closes the connection
+    let response = tokio::select! {
+        response = turn.client.chat(turn.system_prompt, turn.messages) => response,
+        () = cancel.cancelled() => return None,
+    };
+
+    Some((response, turn.sources, turn.search_error))
+}
+
+/// Fold the ways older versions had of turning web search off, the
+/// `enable_web_search` toggle and the "None" search service, into
+/// `SearchMode::Never`.
+fn migrate_search_switches(ctx: &cosmic_config::Config, config: &mut Config) {
+    /// The removed `SearchBackend::None`, as it's stored.
+    #[derive(serde::Deserialize)]
+    enum LegacyBackend {
+        None,
+    }
+
+    let toggled_off = matches!(ctx.get::<bool>("enable_web_search"), Ok(false));
+    let no_backend = ctx.get::<LegacyBackend>("search_backend").is_ok();
+    if !toggled_off && !no_backend {
+        return;
+    }
+    config.search_mode = SearchMode::Never;
+    if no_backend {
+        config.search_backend = SearchBackend::default();
+    }
+    // The old toggle would otherwise turn search off again on every start
+    let _ = ctx.set("enable_web_search", true);
+    let _ = config.write_entry(ctx);
+}
+
+/// Deliver a turn's stream events as messages the moment they arrive.
+///
+/// Keyed by turn, so a new stream replaces the old subscription and
+/// clearing `stream_rx` drops it.
+fn stream_subscription(
+    turn: u64,
+    rx: Arc<Mutex<mpsc::Receiver<StreamEvent>>>,
+) -> Subscription<Message> {
+    let events = stream::unfold(Some(rx), |rx| async move {
+        let rx = rx?;
+        let event = rx.lock().await.recv().await;
+        let done = event.is_none();
+        Some((stream_message(event), (!done).then_some(rx)))
+    });
+
+    Subscription::run_with_id(("ollama-stream", turn), events)
+}
+
+/// The message for a stream event, or for the channel closing.
+fn stream_message(event: Option<StreamEvent>) -> Message {
+    match event {
+        Some(StreamEvent::Chunk(content)) => Message::StreamChunk(content),
+        Some(StreamEvent::Stats {
+            tokens,
+            tokens_per_sec,
+        }) => Message::StreamStats(tokens, tokens_per_sec),
+        Some(StreamEvent::Done(usage)) => Message::StreamDone(usage),
+        Some(StreamEvent::ModelNotFound(model)) => Message::ModelNotFound(model),
+        Some(StreamEvent::Error(err)) => Message::StreamError(err),
+        None => Message::StreamClosed,
+    }
+}
+
+/// Deliver model pull progress as messages.
+fn pull_subscription(
+    name: String,
+    rx: Arc<Mutex<mpsc::Receiver<PullEvent>>>,
+) -> Subscription<Message> {
+    let events = stream::unfold(Some(rx), |rx| async move {
+        let rx = rx?;
+        let event = rx.lock().await.recv().await;
+        let message = match event {
+            Some(PullEvent::Progress { completed, total }) => {
+                Message::PullProgress { completed, total }
+            }
+            Some(PullEvent::Done) => return Some((Message::PullFinished(Ok(())), None)),
+            Some(PullEvent::Error(err)) => return Some((Message::PullFinished(Err(err)), None)),
+            None => {
+                let err = "Pull failed: connection closed early".to_string();
+                return Some((Message::PullFinished(Err(err)), None));
+            }
+        };
+        Some((message, Some(rx)))
+    });
+
+    Subscription::run_with_id(("ollama-pull", name), events)
+}
+
+/// A labelled slider for a numeric setting, saved when released.
+fn slider_setting(
+    label: &'static str,
+    value: f32,
+    range: RangeInclusive<f32>,
+    on_change: fn(f32) -> Message,
+) -> Element<'static, Message> {
+    let spacing = theme::active().cosmic().spacing;
+
+    widget::column()
+        .spacing(spacing.space_xxxs)
+        .push(widget::text::body(format!("{}: {:.2}", label, value)))
+        .push(
+            widget::slider(range, value, on_change)
+                .step(0.05)
+                .on_release(Message::SaveSettings),
+        )
+        .into()
+}
+
+/// A labelled slider for a popup dimension, saved when released. Applies
+/// the next time the popup opens.
+fn popup_size_setting(
+    label: &'static str,
+    value: f32,
+    range: RangeInclusive<f32>,
+    on_change: fn(f32) -> Message,
+) -> Element<'static, Message> {
+    let spacing = theme::active().cosmic().spacing;
+
+    widget::column()
+        .spacing(spacing.space_xxxs)
+        .push(widget::text::body(format!("{}: {:.0} px", label, value)))
+        .push(
+            widget::slider(range, value, on_change)
+                .step(10.0)
+                .on_release(Message::SaveSettings),
+        )
+        .into()
+}
+
+/// A labelled switch for including one context source.
+fn context_toggle(
+    label: &'static str,
+    source: ContextSource,
+    enabled: bool,
+) -> Element<'static, Message> {
+    widget::settings::item(
+        label,
+        widget::toggler(enabled)
+            .on_toggle(move |enabled| Message::SetContextSource(source, enabled)),
+    )
+    .into()
+}
+
+/// Render assistant markdown: code blocks in a monospace card with a copy
+/// button, lists with bullets and bold/italic/code inline spans.
+///
+/// `copied` is the text last copied, whose button shows a check mark.
+/// Long code lines wrap with `wrap_code`; otherwise the block scrolls
+/// sideways.
+fn render_markdown(
+    content: &str,
+    copied: Option<&str>,
+    text_size: f32,
+    wrap_code: bool,
+) -> Element<'static, Message> {
+    let spacing = theme::active().cosmic().spacing;
+
+    let mut column = widget::column().spacing(spacing.space_xs);
+    for block in markdown::parse(content) {
+        let element: Element<'static, Message> = match block {
+            // Long URLs and paths break anywhere rather than overflow
+            Block::Paragraph(inlines) => rich_text(inline_spans(&inlines, false))
+                .size(text_size)
+                .wrapping(Wrapping::WordOrGlyph)
+                .width(Length::Fill)
+                .into(),
+            Block::Heading(_, inlines) => rich_text(inline_spans(&inlines, true))
+                .size(text_size)
+                .width(Length::Fill)
+                .into(),
+            Block::Code { lang, code, closed } => {
+                let copy_icon = if copied == Some(code.as_str()) {
+                    "object-select-symbolic"
+                } else {
+                    "edit-copy-symbolic"
+                };
+                let copy_btn = widget::button::icon(widget::icon::from_name(copy_icon))
+                    .padding(spacing.space_xxxs)
+                    .on_press(Message::CopyText(code.clone()));
+                let code_header = widget::row()
+                    .align_y(Alignment::Center)
+                    .push(widget::text::caption(lang).width(Length::Fill))
+                    .push(copy_btn);
+
+                let code_text = highlight_code(&code, &lang, closed, text_size, wrap_code);
+                let code_view: Element<'static, Message> = if wrap_code {
+                    code_text
+                } else {
+                    widget::scrollable(code_text)
+                        .direction(scrollable::Direction::Horizontal(
+                            scrollable::Scrollbar::new(),
+                        ))
+                        .width(Length::Fill)
+                        .into()
+                };
+
+                widget::container(
+                    widget::column()
+                        .spacing(spacing.space_xxxs)
+                        .push(code_header)
+                        .push(code_view),
+                )
+                .class(theme::Container::Background)
+                .padding(spacing.space_xs)
+                .width(Length::Fill)
+                .into()
+            }
+            Block::ListItem { number, content } => {
+                let marker = match number {
+                    Some(number) => format!("{}.", number),
+                    None => "•".to_string(),
+                };
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .push(widget::text(marker).size(text_size))
+                    .push(
+                        rich_text(inline_spans(&content, false))
+                            .size(text_size)
+                            .width(Length::Fill),
+                    )
+                    .into()
+            }
+        };
+        column = column.push(element);
+    }
+
+    column.width(Length::Fill).into()
+}
+
+/// Monospace code, colored by language once the block is complete.
+///
+/// A block still being streamed stays plain so it isn't re-highlighted
+/// with every chunk; unknown languages are never highlighted. Without
+/// `wrap` each line stays whole and takes its natural width.
+fn highlight_code(
+    code: &str,
+    lang: &str,
+    closed: bool,
+    text_size: f32,
+    wrap: bool,
+) -> Element<'static, Message> {
+    let (wrapping, width) = if wrap {
+        (Wrapping::WordOrGlyph, Length::Fill)
+    } else {
+        (Wrapping::None, Length::Shrink)
+    };
+
+    let Some(tokens) = closed
+        .then(|| highlight::highlight_cached(code, lang))
+        .flatten()
+    else {
+        return widget::text(code.to_string())
+            .size(text_size)
+            .font(cosmic::font::mono())
+            .wrapping(wrapping)
+            .width(width)
+            .into();
+    };
+
+    let theme = theme::active();
+    let cosmic = theme.cosmic();
+    let spans: Vec<Span<'static, (), Font>> = tokens
+        .iter()
+        .map(|token| {
+            let color: Option<Color> = match token.kind {
+                TokenKind::Plain => None,
+                TokenKind::Keyword => Some(cosmic.accent_text_color().into()),
+                TokenKind::String => Some(cosmic.success_color().into()),
+                TokenKind::Number => Some(cosmic.warning_color().into()),
+                TokenKind::Comment => Some(cosmic.palette.neutral_6.into()),
+            };
+            span(token.text.clone())
+                .font(cosmic::font::mono())
+                .color_maybe(color)
+        })
+        .collect();
+
+    rich_text(spans)
+        .size(text_size)
+        .wrapping(wrapping)
+        .width(width)
+        .into()
+}
+
+/// Convert parsed inline runs into styled text spans.
+fn inline_spans(inlines: &[Inline], heading: bool) -> Vec<Span<'static, (), Font>> {
+    inlines
+        .iter()
+        .map(|inline| {
+            let mut font = if inline.code {
+                cosmic::font::mono()
+            } else {
+                cosmic::font::default()
+            };
+            if inline.bold || heading {
+                font.weight = font::Weight::Bold;
+            }
+            if inline.italic {
+                font.style = font::Style::Italic;
+            }
+            span(inline.text.clone()).font(font)
+        })
+        .collect()
+}
+
+/// Whether a message is the user doing something other than confirming a
+/// pending clear.
+fn cancels_clear(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::EditInput(_)
+            | Message::Submit
+            | Message::NewConversation
+            | Message::Regenerate
+            | Message::EditMessage(_)
+            | Message::SwitchConversation(_)
+            | Message::SelectModel(_)
+            | Message::ToggleSettings
+            | Message::ToggleSearch
+            | Message::ClosePopup
+            | Message::TogglePopup
+    )
+}
+
+/// Map popup keyboard events to messages.
+///
+/// - Ctrl+L clears the chat, even while typing.
+/// - Escape closes the popup once the input has let go of it (the first
+///   press unfocuses a focused input).
+/// - Up/Down recall previous prompts.
+fn handle_key_event(event: event::Event, status: event::Status, _id: Id) -> Option<Message> {
+    let event::Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event else {
+        return None;
+    };
+
+    // Text inputs have no use for Ctrl+L, so it works regardless of focus
+    if modifiers.control()
+        && let keyboard::Key::Character(c) = &key
+        && c.eq_ignore_ascii_case("l")
+    {
+        return Some(Message::ClearChat);
+    }
+
+    // Leave keys the focused widget already handled alone
+    if status == event::Status::Captured {
+        return None;
+    }
+
+    match key {
+        keyboard::Key::Named(named) => match named {
+            keyboard::key::Named::Escape => Some(Message::ClosePopup),
+            keyboard::key::Named::ArrowUp => Some(Message::RecallPrevious),
+            keyboard::key::Named::ArrowDown => Some(Message::RecallNext),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 impl cosmic::Application for AppModel {
@@ -117,36 +1317,44 @@ impl cosmic::Application for AppModel {
     ) -> (Self, Task<cosmic::Action<Self::Message>>) {
         let (config, config_ctx) = cosmic_config::Config::new(Self::APP_ID, Config::VERSION)
             .map(|ctx| {
-                let config = match Config::get_entry(&ctx) {
+                let mut config = match Config::get_entry(&ctx) {
                     Ok(config) => config,
                     Err((_errors, config)) => config,
                 };
+                migrate_search_switches(&ctx, &mut config);
                 (config, Some(ctx))
             })
             .unwrap_or_else(|_| (Config::default(), None));
 
-        // Load chat history from disk
-        let saved_history = history::load_history();
-        let messages = if saved_history.messages.is_empty() {
-            // No saved history - show welcome message
-            vec![(
-                "assistant".to_string(),
-                "Hi! I'm your local AI assistant. Copy text for context, then ask me anything."
-                    .to_string(),
-            )]
-        } else {
-            saved_history.to_messages()
-        };
-
-        let app = AppModel {
+        let mut app = AppModel {
             core,
             config,
             config_ctx,
-            messages,
+            conversations: history::list_conversations(),
             ..Default::default()
         };
+        app.refresh_persona_options();
+        let proxy = app.apply_proxy();
+        let limits = app.reset_zero_limits();
+
+        // Reopen the most recently used conversation
+        let conversation = app
+            .conversations
+            .first()
+            .cloned()
+            .unwrap_or_else(Conversation::new);
+        app.open_conversation(conversation);
 
-        (app, Task::none())
+        app.prompt_history = app
+            .messages
+            .iter()
+            .filter(|m| m.role == "user")
+            .map(|m| m.content.clone())
+            .collect();
+        let excess = app.prompt_history.len().saturating_sub(PROMPT_HISTORY_SIZE);
+        app.prompt_history.drain(..excess);
+
+        (app, Task::batch([proxy, limits]))
     }
 
     fn on_close_requested(&self, id: Id) -> Option<Message> {
@@ -154,80 +1362,352 @@ impl cosmic::Application for AppModel {
     }
 
     fn view(&self) -> Element<'_, Self::Message> {
+        // The icon shows at a glance whether an answer is on its way or ready
+        let icon = if self.waiting {
+            GENERATING_ICON
+        } else if self.unread {
+            UNREAD_ICON
+        } else {
+            APP_ID
+        };
         self.core
             .applet
-            .icon_button(APP_ID)
+            .icon_button(icon)
             .on_press(Message::TogglePopup)
             .into()
     }
 
     fn view_window(&self, _id: Id) -> Element<'_, Self::Message> {
         let header = self.build_header();
-        let chat_content = self.build_chat_content();
-        let input_row = self.build_input_row();
 
-        let content = widget::column()
+        let mut content = widget::column()
             .spacing(theme::active().cosmic().spacing.space_xs)
-            .push(header)
-            .push(widget::divider::horizontal::light())
-            .push(chat_content)
-            .push(widget::divider::horizontal::light())
-            .push(input_row)
-            .padding(theme::active().cosmic().spacing.space_s);
+            .push(header);
+
+        if let ConnectionStatus::Disconnected(reason) = &self.connection
+            && !self.offline_dismissed
+        {
+            content = content.push(self.build_offline_banner(reason));
+        }
+
+        if let Some(error) = &self.last_error
+            && self.show_last_error
+        {
+            content = content.push(self.build_last_error(error));
+        }
+
+        if let Some(imported) = &self.pending_import {
+            content = content.push(self.build_import_prompt(imported));
+        }
+
+        if let Some(status) = &self.status {
+            content = content.push(self.build_status(status));
+        }
+
+        let content = content.push(widget::divider::horizontal::light());
+        let content = if self.show_settings {
+            content.push(self.build_settings())
+        } else if self.show_model_info {
+            content.push(self.build_model_info())
+        } else {
+            let content = if self.show_search {
+                content.push(self.build_search_row())
+            } else {
+                content
+            };
+            content
+                .push(self.build_chat_content())
+                .push(widget::divider::horizontal::light())
+                .push(self.build_input_row())
+        }
+        .padding(theme::active().cosmic().spacing.space_s);
 
         self.core.applet.popup_container(content).into()
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        self.core()
+        let config = self
+            .core()
             .watch_config::<Config>(Self::APP_ID)
-            .map(|update| Message::UpdateConfig(update.config))
+            .map(|update| Message::UpdateConfig(update.config));
+
+        let ask = Subscription::run_with_id("dbus-ask", dbus::serve()).map(Message::ExternalAsk);
+
+        let mut subscriptions = vec![config, ask];
+
+        if let Some(rx) = &self.stream_rx {
+            if self.config.stream_polling {
+                let interval = Duration::from_millis(self.config.stream_poll_ms.max(1));
+                subscriptions.push(time::every(interval).map(|_| Message::PollStream));
+            } else {
+                subscriptions.push(stream_subscription(self.turn, rx.clone()));
+            }
+        }
+
+        if let Some(rx) = &self.pull_rx {
+            subscriptions.push(pull_subscription(self.pull_name.clone(), rx.clone()));
+        }
+
+        // Keyboard shortcuts, connection checks and model refreshes only
+        // matter while the popup is open
+        if self.popup.is_some() {
+            subscriptions.push(event::listen_with(handle_key_event));
+            subscriptions
+                .push(time::every(CONNECTION_CHECK_INTERVAL).map(|_| Message::CheckConnection));
+            // Pick up models pulled elsewhere, but leave a response alone
+            if self.config.model_refresh_secs > 0 && !self.waiting {
+                let interval = Duration::from_secs(self.config.model_refresh_secs);
+                subscriptions.push(time::every(interval).map(|_| Message::LoadModels));
+            }
+            // The plain transcript stays still for screen readers
+            if !self.config.plain_text_mode && self.awaiting_first_token() {
+                subscriptions.push(time::every(TYPING_FRAME_INTERVAL).map(|_| Message::TypingTick));
+            }
+        }
+
+        if self.config.reveal_mode == RevealMode::Typewriter && !self.pending_reveal.is_empty() {
+            subscriptions.push(time::every(REVEAL_INTERVAL).map(|_| Message::RevealTick));
+        }
+
+        Subscription::batch(subscriptions)
     }
 
     fn update(&mut self, message: Self::Message) -> Task<cosmic::Action<Self::Message>> {
+        if self.confirming_clear && cancels_clear(&message) {
+            self.confirming_clear = false;
+        }
+
         match message {
             Message::UpdateConfig(config) => {
+                let proxy_changed = config.http_proxy != self.config.http_proxy;
                 self.config = config;
+                self.refresh_model_options();
+                self.refresh_persona_options();
+                let limits = self.reset_zero_limits();
+                if proxy_changed {
+                    return Task::batch([self.apply_proxy(), limits]);
+                }
+                return limits;
+            }
+            Message::EditInput(action) => {
+                let edited = action.is_edit();
+                self.input_editor.perform(action);
+                if edited {
+                    // The editor reports a trailing line break
+                    let text = self.input_editor.text();
+                    self.input_text = text.strip_suffix('\n').unwrap_or(&text).to_string();
+                    self.prompt_history_index = None;
+                }
+            }
+            Message::SetEnterSends(enabled) => {
+                self.config.enter_sends = enabled;
+                return self.save_config();
+            }
+            Message::SetUserBubbleAccent(enabled) => {
+                self.config.user_bubble_accent = enabled;
+                return self.save_config();
+            }
+            Message::SetAlignBubbles(enabled) => {
+                self.config.align_bubbles = enabled;
+                return self.save_config();
             }
-            Message::InputChanged(text) => {
-                self.input_text = text;
+            Message::SetWrapCode(enabled) => {
+                self.config.wrap_code = enabled;
+                return self.save_config();
+            }
+            Message::SetRevealMode(index) => {
+                if let Some(mode) = RevealMode::ALL.get(index) {
+                    self.config.reveal_mode = *mode;
+                    return self.save_config();
+                }
+            }
+            Message::RevealTick => {
+                if self.pending_reveal.is_empty() {
+                    return Task::none();
+                }
+                let count = self.pending_reveal.chars().count();
+                let step = (count / REVEAL_CATCH_UP_TICKS).max(1);
+                let end = self
+                    .pending_reveal
+                    .char_indices()
+                    .nth(step)
+                    .map_or(self.pending_reveal.len(), |(i, _)| i);
+                let revealed: String = self.pending_reveal.drain(..end).collect();
+                self.reveal(&revealed);
+                return self.follow_chat();
             }
             Message::Submit => {
                 return self.handle_submit();
             }
-            Message::StreamReady(rx) => {
+            Message::StreamReady(turn, rx, task, sources, search_error) => {
+                // A turn that was stopped while gathering context
+                if turn != self.turn {
+                    task.abort();
+                    return Task::none();
+                }
                 self.stream_rx = Some(rx);
+                self.stream_task = Some(task);
+                self.attached_sources = Some(sources);
                 // Add empty assistant message that will be filled incrementally
-                self.messages.push(("assistant".to_string(), String::new()));
-                return Task::done(cosmic::Action::App(Message::PollStream));
+                self.messages.push(self.answer(String::new()));
+                let follow = self.follow_chat();
+                return match search_error {
+                    Some(err) => Task::batch([follow, self.show_status(err, true)]),
+                    None => follow,
+                };
             }
             Message::StreamChunk(content) => {
-                // Append to the last message (assistant's streaming response)
-                if let Some((role, text)) = self.messages.last_mut()
-                    && role == "assistant"
+                // Append to the last message (assistant's streaming response),
+                // or hold it back for the other reveal modes
+                match self.config.reveal_mode {
+                    RevealMode::Stream => {
+                        self.flush_reveal();
+                        self.reveal(&content);
+                    }
+                    RevealMode::Instant | RevealMode::Typewriter => {
+                        self.pending_reveal.push_str(&content);
+                    }
+                }
+                let follow = self.follow_chat();
+                return match self.schedule_autosave() {
+                    Some(autosave) => Task::batch([follow, autosave]),
+                    None => follow,
+                };
+            }
+            Message::AutosaveStream(turn) => {
+                self.autosave_pending = false;
+                // The final save happens when the stream ends
+                if turn == self.turn && self.is_streaming() {
+                    // Include what the reveal mode is still holding back,
+                    // without showing it yet
+                    let shown = self.messages.last().map_or(0, |m| m.content.len());
+                    let pending = self.pending_reveal.clone();
+                    self.reveal(&pending);
+                    self.save_history();
+                    if let Some(message) = self.messages.last_mut() {
+                        message.content.truncate(shown);
+                    }
+                }
+            }
+            Message::StreamStats(tokens, tokens_per_sec) => {
+                if let Some(message) = self.messages.last_mut()
+                    && message.role == "assistant"
                 {
-                    text.push_str(&content);
+                    message.stats = Some(GenerationStats {
+                        tokens,
+                        tokens_per_sec,
+                    });
                 }
-                return Task::done(cosmic::Action::App(Message::PollStream));
             }
-            Message::StreamDone => {
-                self.waiting = false;
-                self.stream_rx = None;
+            Message::ResponseComplete {
+                turn,
+                response,
+                sources,
+                search_error,
+            } => {
+                // A turn that was stopped while waiting
+                if turn != self.turn {
+                    return Task::none();
+                }
+                self.attached_sources = Some(sources);
+                match response {
+                    Ok((text, usage)) => {
+                        self.token_usage += usage;
+                        let text = if self.config.strip_reasoning {
+                            markdown::split_thinking(&text).answer
+                        } else {
+                            text
+                        };
+                        self.messages.push(HistoryMessage {
+                            usage,
+                            ..self.answer(text)
+                        });
+                        self.save_history();
+                        self.log_last_turn();
+                        self.last_error = None;
+                    }
+                    Err(err) => {
+                        self.record_error("Chat request", &err);
+                        self.messages.push(HistoryMessage::error(err));
+                    }
+                }
+                let mut tasks = vec![self.follow_chat(), self.finish_stream()];
+                if let Some(err) = search_error {
+                    tasks.push(self.show_status(err, true));
+                }
+                return Task::batch(tasks);
+            }
+            Message::StreamDone(usage) => {
+                // The rest is shown at once so the turn ends promptly
+                self.flush_reveal();
+                self.token_usage += usage;
+                if let Some(message) = self.messages.last_mut()
+                    && message.role == "assistant"
+                {
+                    // The answer counts as sent once it's complete
+                    message.timestamp = history::unix_now().as_secs();
+                    message.usage = usage;
+                    if self.config.strip_reasoning {
+                        message.content = markdown::split_thinking(&message.content).answer;
+                    }
+                }
+                self.unread = self.popup.is_none();
+                self.set_connection(&Ok(()));
+                self.last_error = None;
                 // Save history after response completes
-                let _ = history::save_history(&self.messages);
+                self.save_history();
+                self.log_last_turn();
+                return self.finish_stream();
             }
             Message::StreamError(err) => {
-                self.waiting = false;
-                self.stream_rx = None;
+                self.record_error("Chat request", &err);
+                self.flush_reveal();
                 // Update the last message with error or add new one
-                if let Some((role, text)) = self.messages.last_mut() {
-                    if role == "assistant" && text.is_empty() {
-                        *text = format!("Error: {}", err);
+                if let Some(message) = self.messages.last_mut() {
+                    if message.role == "assistant" && message.content.is_empty() {
+                        *message = HistoryMessage::error(err);
                     } else {
-                        self.messages
-                            .push(("assistant".to_string(), format!("Error: {}", err)));
+                        self.messages.push(HistoryMessage::error(err));
                     }
                 }
+                return self.finish_stream();
+            }
+            Message::ModelNotFound(model) => {
+                // Drop the empty bubble pushed by StreamReady
+                if self
+                    .messages
+                    .last()
+                    .is_some_and(|m| m.role == "assistant" && m.content.is_empty())
+                {
+                    self.messages.pop();
+                }
+                self.stream_rx = None;
+
+                // Retry once with the fallback, unless it's what just failed
+                if let Some(fallback) = self
+                    .config
+                    .fallback_model
+                    .clone()
+                    .filter(|fallback| !fallback.is_empty() && *fallback != model)
+                    && let Some(mut request) = self.last_request.clone()
+                {
+                    request.model = fallback.clone();
+                    let status = self.show_status(
+                        format!("'{}' is unavailable, using '{}' instead", model, fallback),
+                        false,
+                    );
+                    return Task::batch([status, self.start_turn(request)]);
+                }
+
+                let err = ollama::model_not_found(&model);
+                self.missing_model = Some(model);
+                return Task::done(cosmic::Action::App(Message::StreamError(err)));
+            }
+            Message::StreamClosed => {
+                // Done and error events already finished the stream
+                if self.stream_rx.is_some() {
+                    return self.finish_stream();
+                }
             }
             Message::PollStream => {
                 return self.poll_stream();
@@ -235,44 +1715,149 @@ impl cosmic::Application for AppModel {
             Message::TogglePopup => {
                 return self.handle_toggle_popup();
             }
+            Message::ClosePopup => {
+                if let Some(p) = self.popup.take() {
+                    return destroy_popup(p);
+                }
+            }
             Message::PopupClosed(id) => {
                 if self.popup.as_ref() == Some(&id) {
                     self.popup = None;
                 }
+                // A stray click closes the popup; keep what was being typed
+                self.save_draft();
             }
             Message::ClearChat => {
+                if !self.confirming_clear {
+                    self.confirming_clear = true;
+                    self.confirm_clear_id += 1;
+                    let id = self.confirm_clear_id;
+                    return Task::perform(tokio::time::sleep(CLEAR_CONFIRM_TIMEOUT), move |_| {
+                        cosmic::Action::App(Message::CancelClear(id))
+                    });
+                }
+                self.confirming_clear = false;
+                self.chat_window = None;
+                self.chat_anchor = None;
+                self.token_usage = TokenUsage::default();
+                self.missing_model = None;
                 self.messages.clear();
-                self.messages.push((
-                    "assistant".to_string(),
-                    "Chat cleared. How can I help?".to_string(),
-                ));
-                // Clear saved history when starting new chat
-                let _ = history::clear_history();
+                self.messages
+                    .push(greeting("Chat cleared. How can I help?"));
+                // Only the active conversation is cleared; with its file
+                // gone it leaves the picker until its next message
+                let id = self.conversation.id;
+                let _ = history::clear_history(id);
+                self.conversation.title.clear();
+                self.conversations.retain(|c| c.id != id);
+                self.refresh_conversation_titles();
             }
-            Message::LoadModels => {
-                if self.loading_models {
-                    return Task::none();
+            Message::CancelClear(id) => {
+                if self.confirm_clear_id == id {
+                    self.confirming_clear = false;
                 }
-                self.loading_models = true;
-                let url = self.config.ollama_url.clone();
-                return Task::perform(
-                    async move { OllamaClient::list_models(&url).await },
+            }
+            Message::Regenerate => {
+                return self.regenerate();
+            }
+            Message::ToggleRegenerateModels => {
+                self.choosing_regenerate_model = !self.choosing_regenerate_model;
+            }
+            Message::RegenerateWith(index) => {
+                self.choosing_regenerate_model = false;
+                // The conversation keeps its model; only this answer changes
+                if let Some(model) = self.available_models.get(index) {
+                    let model = model.name.clone();
+                    return self.regenerate_with(model);
+                }
+            }
+            Message::EditMessage(index) => {
+                // Truncating under a stream would lose the bubble it writes to
+                if self.waiting {
+                    return Task::none();
+                }
+                if let Some(message) = self.messages.get(index)
+                    && message.role == "user"
+                {
+                    self.set_input(message.content.clone());
+                    // The image goes back on the input to be sent again
+                    if let Some((name, data)) = message
+                        .images
+                        .first()
+                        .and_then(|name| Some((name.clone(), history::load_image(name)?)))
+                    {
+                        self.image = Some(ImageAttachment { name, data });
+                    }
+                    self.prompt_history_index = None;
+                    self.truncate_messages(index);
+                    self.attached_sources = None;
+                    self.save_history();
+                }
+            }
+            Message::NewConversation => {
+                if self.waiting {
+                    return self.show_status(
+                        "Wait for the current response before switching".to_string(),
+                        true,
+                    );
+                }
+                self.open_conversation(Conversation::new());
+            }
+            Message::SwitchConversation(id) => {
+                if id == self.conversation.id {
+                    return Task::none();
+                }
+                if self.waiting {
+                    return self.show_status(
+                        "Wait for the current response before switching".to_string(),
+                        true,
+                    );
+                }
+                if let Some(conversation) = self.conversations.iter().find(|c| c.id == id) {
+                    self.open_conversation(conversation.clone());
+                    return self.load_model_details(self.active_model().to_string());
+                }
+            }
+            Message::LoadModels => {
+                if self.loading_models {
+                    return Task::none();
+                }
+                self.loading_models = true;
+                let url = self.config.ollama_url.clone();
+                let version_url = url.clone();
+                let api_key = self.config.api_key.clone();
+                let version_key = api_key.clone();
+                let format = self.config.api_format;
+                let max_retries = self.config.max_retries;
+                let models_task = Task::perform(
+                    async move { OllamaClient::list_models(&url, &api_key, format, max_retries).await },
                     |result| cosmic::Action::App(Message::ModelsLoaded(result)),
                 );
+                // /api/version only exists on Ollama itself
+                if format != ApiFormat::Ollama {
+                    self.server = None;
+                    return models_task;
+                }
+                let probe_task = Task::perform(
+                    async move { OllamaClient::server_info(&version_url, &version_key).await },
+                    |result| cosmic::Action::App(Message::ServerProbed(result)),
+                );
+                return Task::batch([models_task, probe_task]);
             }
             Message::ModelsLoaded(result) => {
                 self.loading_models = false;
+                self.set_connection(&result);
                 match result {
                     Ok(models) => {
-                        // Cache display options for dropdown
-                        self.model_options = models
-                            .iter()
-                            .map(|m| format!("{} ({})", m.name, m.display_size))
-                            .collect();
                         self.available_models = models;
+                        self.refresh_model_options();
+                        self.last_error = None;
+                        return self.load_model_details(self.active_model().to_string());
                     }
-                    Err(_) => {
-                        // Silently fail - user can still type model name in config
+                    Err(err) => {
+                        self.record_error("Loading models", &err);
+                        // The offline banner explains why; the model name
+                        // can still be typed in the settings
                         self.available_models.clear();
                         self.model_options.clear();
                     }
@@ -280,11 +1865,736 @@ impl cosmic::Application for AppModel {
             }
             Message::SelectModel(index) => {
                 if let Some(model) = self.available_models.get(index) {
-                    self.config.model = model.name.clone();
-                    // Save to config
-                    if let Some(ctx) = &self.config_ctx {
-                        let _ = self.config.write_entry(ctx);
+                    let name = model.name.clone();
+                    if name == self.active_model() {
+                        return Task::none();
+                    }
+                    // Switching mid-response asks the new model instead
+                    let resend = self.waiting;
+                    let stop_task = self.stop_generation();
+
+                    self.conversation.model = name.clone();
+                    self.refresh_model_options();
+                    let details_task = self.load_model_details(name.clone());
+
+                    // A chat that hasn't started yet also sets the default
+                    // for new ones
+                    if !self.messages.iter().any(|m| m.role == "user") {
+                        self.config.model = name;
+                        return Task::batch([stop_task, self.save_config(), details_task]);
+                    }
+                    self.save_history();
+                    if resend {
+                        // Replaces the partial answer from the old model
+                        return Task::batch([stop_task, details_task, self.regenerate()]);
+                    }
+                    return Task::batch([stop_task, details_task]);
+                }
+            }
+            Message::SelectPersona(index) => {
+                // Earlier answers keep the persona they were written with;
+                // only later turns use the new one
+                self.conversation.persona = index
+                    .checked_sub(1)
+                    .and_then(|i| self.config.personas.get(i))
+                    .map(|p| p.name.clone())
+                    .unwrap_or_default();
+                if self.messages.iter().any(|m| m.role == "user") {
+                    self.save_history();
+                }
+            }
+            Message::ExternalAsk(request) => {
+                self.set_input(request.query);
+                self.prompt_history_index = None;
+                let open = if self.popup.is_none() {
+                    self.handle_toggle_popup()
+                } else {
+                    Task::none()
+                };
+                if request.submit {
+                    return Task::batch([open, self.handle_submit()]);
+                }
+                return open;
+            }
+            Message::ServerProbed(result) => {
+                // Pre-/api/version servers are treated like unknown versions
+                self.server = result.ok();
+            }
+            Message::ModelDetailsLoaded(name, result) => {
+                // Missing details just means `think` is omitted for this model
+                match result {
+                    Ok(details) => {
+                        self.model_details.insert(name, details);
+                    }
+                    Err(err) if self.show_model_info => {
+                        self.show_model_info = false;
+                        return self.show_status(err, true);
+                    }
+                    Err(_) => {}
+                }
+            }
+            Message::ToggleModelInfo => {
+                self.show_model_info = !self.show_model_info;
+                if self.show_model_info {
+                    self.show_settings = false;
+                    return self.load_model_details(self.active_model().to_string());
+                }
+            }
+            Message::RemoveQueued(index) => {
+                if index < self.queued.len() {
+                    self.queued.remove(index);
+                }
+            }
+            Message::RefreshContext => {
+                self.refresh_static_context = true;
+                return self.show_status(
+                    "System info will be attached to the next message".to_string(),
+                    false,
+                );
+            }
+            Message::CycleSearchMode => {
+                let next = self.next_search_mode().next();
+                // Back at the configured mode means no override
+                self.search_mode = (next != self.config.search_mode).then_some(next);
+            }
+            Message::RecallPrevious => {
+                // Only take over the arrow keys when the input is empty or
+                // still showing a recalled prompt
+                if self.prompt_history.is_empty()
+                    || (self.prompt_history_index.is_none() && !self.input_text.is_empty())
+                {
+                    return Task::none();
+                }
+                let index = match self.prompt_history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None => self.prompt_history.len() - 1,
+                };
+                self.prompt_history_index = Some(index);
+                self.set_input(self.prompt_history[index].clone());
+            }
+            Message::RecallNext => {
+                if let Some(index) = self.prompt_history_index {
+                    if index + 1 < self.prompt_history.len() {
+                        self.prompt_history_index = Some(index + 1);
+                        self.set_input(self.prompt_history[index + 1].clone());
+                    } else {
+                        self.prompt_history_index = None;
+                        self.set_input(String::new());
+                    }
+                }
+            }
+            Message::ChatScrolled(viewport) => {
+                // Content shorter than the view reports NaN
+                let scrolled_up = viewport.relative_offset().y < 0.99;
+                let heights = self.message_heights();
+                if !scrolled_up {
+                    self.chat_window = None;
+                    self.chat_anchor = None;
+                } else if !self.chat_scrolled_up {
+                    // Carry on from the messages built while following
+                    self.chat_window = Some(self.built_window(&heights));
+                }
+                self.chat_scrolled_up = scrolled_up;
+                self.chat_viewport = Some(viewport);
+
+                // Once the window's new top is laid out, scroll by the
+                // height it added or removed so the same messages stay in
+                // view
+                if let Some(height) = self.chat_anchor.take() {
+                    let moved = viewport.content_bounds().height - height;
+                    if moved != 0.0 {
+                        return scrollable::scroll_to(
+                            CHAT_SCROLL_ID.clone(),
+                            scrollable::AbsoluteOffset {
+                                x: 0.0,
+                                y: viewport.absolute_offset().y + moved,
+                            },
+                        );
+                    }
+                }
+
+                if scrolled_up
+                    && let Some((window, top_moved)) = shift_chat_window(
+                        &self.built_window(&heights),
+                        &heights,
+                        viewport.absolute_offset().y,
+                        viewport.bounds().height,
+                        viewport.content_bounds().height,
+                    )
+                {
+                    if top_moved {
+                        self.chat_anchor = Some(viewport.content_bounds().height);
+                    }
+                    self.chat_window = Some(window);
+                }
+            }
+            Message::AttachFile => {
+                return Task::perform(
+                    async {
+                        let dialog = file_chooser::open::Dialog::new().title("Attach a text file");
+                        let response = dialog.open_file().await.map_err(|e| e.to_string())?;
+                        let path = response
+                            .url()
+                            .to_file_path()
+                            .map_err(|_| "Only local files can be attached".to_string())?;
+                        tokio::task::spawn_blocking(move || Attachment::read(&path))
+                            .await
+                            .map_err(|e| e.to_string())?
+                    },
+                    |result| cosmic::Action::App(Message::FileAttached(result)),
+                );
+            }
+            Message::FileAttached(result) => match result {
+                Ok(attachment) => self.attachment = Some(attachment),
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::RemoveAttachment => {
+                self.attachment = None;
+            }
+            Message::AttachText(source) => {
+                let primary = source == ContextSource::Selection;
+                let limit = self.config.clipboard_limit();
+                return Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            context::capture_clipboard(primary, limit)
+                        })
+                        .await
+                        .map_err(|e| e.to_string())?
+                    },
+                    move |result| cosmic::Action::App(Message::TextAttached(source, result)),
+                );
+            }
+            Message::TextAttached(source, result) => match result {
+                Ok(text) => {
+                    if let Some(slot) = self.attached_text(source) {
+                        *slot = Some(text);
+                    }
+                }
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::RemoveText(source) => {
+                if let Some(slot) = self.attached_text(source) {
+                    *slot = None;
+                }
+            }
+            Message::AttachImage => {
+                if !self.accepts_images() {
+                    return Task::none();
+                }
+                return Task::perform(
+                    async {
+                        let dialog = file_chooser::open::Dialog::new().title("Attach an image");
+                        let response = dialog.open_file().await.map_err(|e| e.to_string())?;
+                        let path = response
+                            .url()
+                            .to_file_path()
+                            .map_err(|_| "Only local files can be attached".to_string())?;
+                        // Decoding and scaling a photo takes a moment
+                        tokio::task::spawn_blocking(move || ImageAttachment::read(&path))
+                            .await
+                            .map_err(|e| e.to_string())?
+                    },
+                    |result| cosmic::Action::App(Message::ImageAttached(result)),
+                );
+            }
+            Message::ImageAttached(result) => match result {
+                Ok(image) => self.image = Some(image),
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::RemoveImage => {
+                self.image = None;
+            }
+            Message::ImportConversation => {
+                return Task::perform(
+                    async {
+                        let dialog =
+                            file_chooser::open::Dialog::new().title("Import a conversation");
+                        let response = dialog.open_file().await.map_err(|e| e.to_string())?;
+                        let path = response
+                            .url()
+                            .to_file_path()
+                            .map_err(|_| "Only local files can be imported".to_string())?;
+                        tokio::task::spawn_blocking(move || history::import_from_path(&path))
+                            .await
+                            .map_err(|e| e.to_string())?
+                    },
+                    |result| cosmic::Action::App(Message::ConversationImported(result)),
+                );
+            }
+            Message::ExportConversation => {
+                // The greeting before the first question isn't worth keeping
+                let Some(start) = self.messages.iter().position(|m| m.role == "user") else {
+                    return self.show_status("Nothing to export yet".to_string(), true);
+                };
+                let document = history::export_markdown(&self.messages[start..]);
+                let file_name = format!("chat-{}.md", self.conversation.id);
+                return Task::perform(
+                    async move {
+                        let dialog = file_chooser::save::Dialog::new()
+                            .title("Export conversation")
+                            .file_name(file_name.clone());
+                        let path = match dialog.save_file().await {
+                            Ok(response) => response
+                                .url()
+                                .and_then(|url| url.to_file_path().ok())
+                                .ok_or_else(|| "Only local files can be written".to_string())?,
+                            Err(file_chooser::Error::Cancelled) => return Ok(None),
+                            // Without a portal, fall back to ~/Downloads
+                            Err(_) => history::downloads_dir()
+                                .ok_or_else(|| "No Downloads folder to export to".to_string())?
+                                .join(file_name),
+                        };
+                        tokio::fs::write(&path, document)
+                            .await
+                            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+                        Ok(Some(path))
+                    },
+                    |result| cosmic::Action::App(Message::ConversationExported(result)),
+                );
+            }
+            Message::ConversationExported(result) => match result {
+                Ok(Some(path)) => {
+                    return self.show_status(format!("Exported to {}", path.display()), false);
+                }
+                Ok(None) => {}
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::ConversationImported(result) => match result {
+                Ok(imported) => {
+                    self.pending_import = Some(imported);
+                    // Nothing to merge into; no need to ask
+                    if !self.messages.iter().any(|m| m.role == "user") {
+                        return self.apply_import(false);
+                    }
+                }
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::ApplyImport(merge) => {
+                return self.apply_import(merge);
+            }
+            Message::CancelImport => {
+                self.pending_import = None;
+            }
+            Message::CancelStream => {
+                // Stopping on purpose drops what was queued behind it too
+                self.queued.clear();
+                return self.stop_generation();
+            }
+            Message::CopyText(text) => {
+                let to_copy = text.clone();
+                return Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || context::copy_to_clipboard(&to_copy))
+                            .await
+                            .map_err(|e| e.to_string())?
+                    },
+                    move |result| cosmic::Action::App(Message::Copied(text.clone(), result)),
+                );
+            }
+            Message::Copied(text, result) => match result {
+                Ok(()) => {
+                    self.copied = Some(text.clone());
+                    return Task::perform(
+                        async { tokio::time::sleep(Duration::from_secs(2)).await },
+                        move |_| cosmic::Action::App(Message::ClearCopied(text.clone())),
+                    );
+                }
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::ClearCopied(text) => {
+                if self.copied.as_ref() == Some(&text) {
+                    self.copied = None;
+                }
+            }
+            Message::ToggleThoughts => {
+                self.show_thoughts = !self.show_thoughts;
+            }
+            Message::AdjustFontScale(steps) => {
+                let scale = self.config.text_scale() + f32::from(steps) * FONT_SCALE_STEP;
+                self.config.font_scale =
+                    scale.clamp(config::MIN_FONT_SCALE, config::MAX_FONT_SCALE);
+                return self.save_config();
+            }
+            Message::SetPopupWidth(width) => {
+                self.config.popup_width = width.round();
+            }
+            Message::SetPopupHeight(height) => {
+                self.config.popup_height = height.round();
+            }
+            Message::SetStripReasoning(strip) => {
+                self.config.strip_reasoning = strip;
+                return self.save_config();
+            }
+            Message::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                if self.show_settings {
+                    self.show_model_info = false;
+                    self.url_input = self.config.ollama_url.clone();
+                    self.model_input = self.config.model.clone();
+                    self.timeout_input = self.config.timeout_secs.to_string();
+                    self.connection_test = None;
+                    self.prompt_editor =
+                        text_editor::Content::with_text(&self.config.system_prompt);
+                    if let SearchBackend::SearXNG { base_url } = &self.config.search_backend {
+                        self.searxng_url = base_url.clone();
+                    }
+                }
+            }
+            Message::ToggleSearch => {
+                self.show_search = !self.show_search;
+                // Closing the field restores the whole conversation
+                self.search_query.clear();
+                if self.show_search {
+                    return widget::text_input::focus(SEARCH_INPUT_ID.clone());
+                }
+                return self.follow_chat();
+            }
+            Message::SearchChanged(query) => {
+                self.search_query = query;
+            }
+            Message::EditSystemPrompt(action) => {
+                self.prompt_editor.perform(action);
+            }
+            Message::SaveSystemPrompt => {
+                self.config.system_prompt = self.prompt_editor.text().trim_end().to_string();
+                return self.save_config();
+            }
+            Message::ResetSystemPrompt => {
+                self.config.system_prompt = ollama::DEFAULT_SYSTEM_PROMPT.to_string();
+                self.prompt_editor = text_editor::Content::with_text(&self.config.system_prompt);
+                return self.save_config();
+            }
+            Message::SetTemperature(value) => {
+                self.config.temperature = (value * 100.0).round() / 100.0;
+            }
+            Message::SetTopP(value) => {
+                self.config.top_p = (value * 100.0).round() / 100.0;
+            }
+            Message::SetNumCtx(index) => {
+                if let Some(num_ctx) = NUM_CTX_VALUES.get(index) {
+                    self.config.num_ctx = *num_ctx;
+                    return self.save_config();
+                }
+            }
+            Message::StopInputChanged(text) => {
+                self.stop_input = text;
+            }
+            Message::AddStopSequence => {
+                // Typed escapes stand for the whitespace they name
+                let stop = self.stop_input.replace("\\n", "\n").replace("\\t", "\t");
+                self.stop_input.clear();
+                if stop.is_empty() || self.config.stop_sequences.contains(&stop) {
+                    return Task::none();
+                }
+                self.config.stop_sequences.push(stop);
+                return self.save_config();
+            }
+            Message::RemoveStopSequence(index) => {
+                if index < self.config.stop_sequences.len() {
+                    self.config.stop_sequences.remove(index);
+                    return self.save_config();
+                }
+            }
+            Message::SaveSettings => {
+                return self.save_config();
+            }
+            Message::SetApiFormat(index) => {
+                if let Some(format) = ApiFormat::ALL.get(index) {
+                    self.config.api_format = *format;
+                    self.model_details.clear();
+                    let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
+                    return Task::batch([self.save_config(), load_task]);
+                }
+            }
+            Message::TypingTick => {
+                self.typing_frame = self.typing_frame.wrapping_add(1);
+            }
+            Message::CheckConnection => {
+                // A streaming response already proves the server is up
+                if self.waiting {
+                    return Task::none();
+                }
+                let url = self.config.ollama_url.clone();
+                let api_key = self.config.api_key.clone();
+                let format = self.config.api_format;
+                return Task::perform(
+                    async move { OllamaClient::ping(&url, &api_key, format).await },
+                    |result| cosmic::Action::App(Message::ConnectionChecked(result)),
+                );
+            }
+            Message::ConnectionChecked(result) => {
+                let was_disconnected = matches!(self.connection, ConnectionStatus::Disconnected(_));
+                self.set_connection(&result);
+                // Fill the model list once a server that was down comes back
+                if was_disconnected && self.connection == ConnectionStatus::Connected {
+                    return Task::done(cosmic::Action::App(Message::LoadModels));
+                }
+            }
+            Message::RetryConnection => {
+                return Task::batch([
+                    Task::done(cosmic::Action::App(Message::LoadModels)),
+                    Task::done(cosmic::Action::App(Message::CheckConnection)),
+                ]);
+            }
+            Message::DismissOffline => {
+                self.offline_dismissed = true;
+            }
+            Message::ToggleLastError => {
+                self.show_last_error = !self.show_last_error;
+            }
+            Message::DismissLastError => {
+                self.last_error = None;
+                self.show_last_error = false;
+            }
+            Message::ToggleManageModels => {
+                self.manage_models = !self.manage_models;
+                self.confirm_delete = None;
+            }
+            Message::DeleteModel(index) => {
+                if let Some(model) = self.available_models.get(index) {
+                    // The active model has to be switched away from first
+                    if model.name == self.active_model() {
+                        return self.show_status(
+                            format!("Switch to another model before deleting {}", model.name),
+                            true,
+                        );
+                    }
+                    self.confirm_delete = Some(model.name.clone());
+                }
+            }
+            Message::CancelDelete => {
+                self.confirm_delete = None;
+            }
+            Message::ConfirmDelete => {
+                if let Some(name) = self.confirm_delete.take() {
+                    let url = self.config.ollama_url.clone();
+                    let api_key = self.config.api_key.clone();
+                    return Task::perform(
+                        async move {
+                            let result = OllamaClient::delete_model(&url, &api_key, &name).await;
+                            (name, result)
+                        },
+                        |(name, result)| cosmic::Action::App(Message::ModelDeleted(name, result)),
+                    );
+                }
+            }
+            Message::ModelDeleted(name, result) => match result {
+                Ok(()) => {
+                    self.model_details.remove(&name);
+                    let status = self.show_status(format!("Deleted {}", name), false);
+                    let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
+                    return Task::batch([status, load_task]);
+                }
+                Err(err) => return self.show_status(err, true),
+            },
+            Message::PullNameChanged(name) => {
+                self.pull_name = name;
+            }
+            Message::PullModel => {
+                let name = self.pull_name.trim().to_string();
+                if name.is_empty() || self.pull_rx.is_some() {
+                    return Task::none();
+                }
+                self.pull_name = name.clone();
+                self.pull_progress = (0, 0);
+                let url = self.config.ollama_url.clone();
+                let api_key = self.config.api_key.clone();
+                return Task::perform(
+                    async move { OllamaClient::pull_model(&url, &api_key, &name).await },
+                    |rx| cosmic::Action::App(Message::PullStarted(Arc::new(Mutex::new(rx)))),
+                );
+            }
+            Message::PullMissingModel => {
+                if let Some(name) = self.missing_model.take() {
+                    // The management rows show the progress
+                    self.manage_models = true;
+                    self.pull_name = name;
+                    return Task::done(cosmic::Action::App(Message::PullModel));
+                }
+            }
+            Message::PullStarted(rx) => {
+                self.pull_rx = Some(rx);
+            }
+            Message::PullProgress { completed, total } => {
+                // Status-only lines (verifying, writing manifest) carry no sizes
+                if total > 0 {
+                    self.pull_progress = (completed, total);
+                }
+            }
+            Message::PullFinished(result) => {
+                self.pull_rx = None;
+                match result {
+                    Ok(()) => {
+                        let name = std::mem::take(&mut self.pull_name);
+                        let status = self.show_status(format!("Pulled {}", name), false);
+                        let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
+                        return Task::batch([status, load_task]);
                     }
+                    Err(err) => return self.show_status(err, true),
+                }
+            }
+            Message::SetStreaming(enabled) => {
+                self.config.streaming = enabled;
+                return self.save_config();
+            }
+            Message::SetRawCompletion(enabled) => {
+                self.config.raw_completion = enabled;
+                return self.save_config();
+            }
+            Message::SetKeepAlive(index) => {
+                if let Some(value) = KEEP_ALIVE_VALUES.get(index) {
+                    self.config.keep_alive = value.to_string();
+                    return self.save_config();
+                }
+            }
+            Message::SetSearchMode(index) => {
+                if let Some(mode) = SearchMode::ALL.get(index) {
+                    self.config.search_mode = *mode;
+                    self.search_mode = None;
+                    return self.save_config();
+                }
+            }
+            Message::SetSearchBackend(index) => {
+                self.config.search_backend = match index {
+                    0 => SearchBackend::DuckDuckGo,
+                    _ => SearchBackend::SearXNG {
+                        base_url: self.searxng_url.trim().to_string(),
+                    },
+                };
+                return self.save_config();
+            }
+            Message::SearxngUrlChanged(url) => {
+                self.searxng_url = url;
+            }
+            Message::SaveSearxngUrl => {
+                let base_url = self.searxng_url.trim().to_string();
+                self.config.search_backend = SearchBackend::SearXNG { base_url };
+                return self.save_config();
+            }
+            Message::UrlChanged(url) => {
+                self.url_input = url;
+                self.connection_test = None;
+                return self.save_settings_later();
+            }
+            Message::SaveUrl => {
+                let url = self.url_input.trim().to_string();
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return self.show_status(
+                        "The server URL must start with http:// or https://".to_string(),
+                        true,
+                    );
+                }
+                self.config.ollama_url = url;
+                self.model_details.clear();
+                let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
+                let check_task = Task::done(cosmic::Action::App(Message::CheckConnection));
+                return Task::batch([self.save_config(), load_task, check_task]);
+            }
+            Message::TestConnection => {
+                self.connection_test = None;
+                let url = self.url_input.trim().to_string();
+                let api_key = self.config.api_key.clone();
+                let format = self.config.api_format;
+                return Task::perform(
+                    // One attempt: a test shouldn't wait out the retries
+                    async move {
+                        let result = OllamaClient::list_models(&url, &api_key, format, 0).await;
+                        (url, result)
+                    },
+                    |(url, result)| cosmic::Action::App(Message::ConnectionTested(url, result)),
+                );
+            }
+            Message::ConnectionTested(url, result) => {
+                // The URL may have been edited while the test ran
+                if url == self.url_input.trim() {
+                    self.connection_test = Some(result.map(|models| models.len()));
+                }
+            }
+            Message::ModelInputChanged(model) => {
+                self.model_input = model;
+                return self.save_settings_later();
+            }
+            Message::SaveModel => {
+                let model = self.model_input.trim().to_string();
+                if model.is_empty() {
+                    return self.show_status("Enter a model name".to_string(), true);
+                }
+                self.config.model = model;
+                self.refresh_model_options();
+                return self.save_config();
+            }
+            Message::TimeoutChanged(timeout) => {
+                self.timeout_input = timeout;
+                return self.save_settings_later();
+            }
+            Message::SaveEditedSettings(id) => {
+                if id != self.settings_edit_id {
+                    return Task::none();
+                }
+                // Values that wouldn't be accepted wait; Enter explains why
+                let url = self.url_input.trim();
+                let model = self.model_input.trim();
+                let timeout = self.timeout_input.trim().parse::<u64>().ok();
+                let mut saves = Vec::new();
+                if url != self.config.ollama_url
+                    && (url.starts_with("http://") || url.starts_with("https://"))
+                {
+                    saves.push(Message::SaveUrl);
+                }
+                if !model.is_empty() && model != self.config.model {
+                    saves.push(Message::SaveModel);
+                }
+                if timeout.is_some_and(|secs| secs > 0 && secs != self.config.timeout_secs) {
+                    saves.push(Message::SaveTimeout);
+                }
+                return Task::batch(
+                    saves
+                        .into_iter()
+                        .map(|message| Task::done(cosmic::Action::App(message))),
+                );
+            }
+            Message::SaveTimeout => match self.timeout_input.trim().parse::<u64>() {
+                Ok(secs) if secs > 0 => {
+                    self.config.timeout_secs = secs;
+                    return self.save_config();
+                }
+                _ => {
+                    return self.show_status(
+                        "The timeout must be a whole number of seconds".to_string(),
+                        true,
+                    );
+                }
+            },
+            Message::ApiKeyChanged(key) => {
+                self.config.api_key = key;
+            }
+            Message::ToggleApiKeyVisible => {
+                self.api_key_visible = !self.api_key_visible;
+            }
+            Message::SaveApiKey => {
+                self.config.api_key = self.config.api_key.trim().to_string();
+                self.model_details.clear();
+                let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
+                return Task::batch([self.save_config(), load_task]);
+            }
+            Message::SetContextSource(source, enabled) => {
+                let setting = match source {
+                    ContextSource::Clipboard => &mut self.config.include_clipboard,
+                    ContextSource::Selection => &mut self.config.include_selection,
+                    ContextSource::SystemInfo => &mut self.config.include_system_info,
+                    ContextSource::RecentErrors => &mut self.config.include_recent_errors,
+                };
+                *setting = enabled;
+                return self.save_config();
+            }
+            Message::ToggleConcise => {
+                self.config.concise_mode = !self.config.concise_mode;
+                return self.save_config();
+            }
+            Message::ClearStatus(id) => {
+                if self.status_id == id {
+                    self.status = None;
                 }
             }
         }
@@ -304,157 +2614,2192 @@ impl AppModel {
         // Build model selector
         let model_widget: Element<'_, Message> = if self.model_options.is_empty() {
             // No models loaded yet - show current model as text
-            widget::text::body(&self.config.model)
+            widget::text::body(self.active_model())
                 .width(Length::Fill)
                 .into()
         } else {
-            // Find current model index
+            // Find current model index; a configured model that isn't
+            // installed is listed as the extra trailing entry
             let selected = self
                 .available_models
                 .iter()
-                .position(|m| m.name == self.config.model);
+                .position(|m| m.name == self.active_model())
+                .or_else(|| {
+                    (self.model_options.len() > self.available_models.len())
+                        .then_some(self.available_models.len())
+                });
 
             widget::dropdown(&self.model_options, selected, Message::SelectModel)
                 .width(Length::Fill)
                 .into()
         };
 
-        let clear_btn = widget::button::icon(widget::icon::from_name("edit-clear-symbolic"))
+        let clear_btn: Element<'_, Message> = if self.confirming_clear {
+            widget::button::destructive("Confirm?")
+                .on_press(Message::ClearChat)
+                .into()
+        } else {
+            widget::button::icon(widget::icon::from_name("edit-clear-symbolic"))
+                .padding(spacing.space_xxs)
+                .on_press(Message::ClearChat)
+                .into()
+        };
+
+        let search_btn = widget::button::icon(widget::icon::from_name("edit-find-symbolic"))
             .padding(spacing.space_xxs)
-            .on_press(Message::ClearChat);
+            .selected(self.show_search)
+            .on_press(Message::ToggleSearch);
 
-        widget::row()
+        let settings_btn = widget::button::icon(widget::icon::from_name("emblem-system-symbolic"))
+            .padding(spacing.space_xxs)
+            .selected(self.show_settings)
+            .on_press(Message::ToggleSettings);
+
+        let import_btn = widget::button::icon(widget::icon::from_name("document-open-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::ImportConversation);
+
+        let export_btn = widget::button::icon(widget::icon::from_name("document-save-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::ExportConversation);
+
+        let mut row = widget::row()
             .align_y(Alignment::Center)
             .spacing(spacing.space_xs)
-            .push(model_widget)
-            .push(clear_btn)
-            .into()
-    }
+            .push(model_widget);
 
-    fn build_chat_content(&self) -> Element<'_, Message> {
-        let spacing = theme::active().cosmic().spacing;
-        let mut chat_column = widget::column().spacing(spacing.space_xs);
+        if self.last_error.is_some() {
+            let error_btn =
+                widget::button::icon(widget::icon::from_name("dialog-warning-symbolic"))
+                    .padding(spacing.space_xxs)
+                    .selected(self.show_last_error)
+                    .on_press(Message::ToggleLastError);
+            row = row.push(error_btn);
+        }
 
-        for (role, content) in &self.messages {
-            let message_widget = self.build_message_bubble(role, content);
-            chat_column = chat_column.push(message_widget);
+        // Pulling, deleting and model details are part of the native Ollama
+        // API only
+        if self.config.api_format == ApiFormat::Ollama {
+            let info_btn = widget::button::icon(widget::icon::from_name("help-about-symbolic"))
+                .padding(spacing.space_xxs)
+                .selected(self.show_model_info)
+                .on_press(Message::ToggleModelInfo);
+            row = row.push(info_btn);
+            let pull_btn =
+                widget::button::icon(widget::icon::from_name("folder-download-symbolic"))
+                    .padding(spacing.space_xxs)
+                    .selected(self.manage_models)
+                    .on_press(Message::ToggleManageModels);
+            row = row.push(pull_btn);
         }
 
-        // Only show "Thinking..." if we're waiting and the stream hasn't started yet
-        // (i.e., the last message is empty or doesn't exist from streaming)
-        let show_thinking = self.waiting && self.stream_rx.is_none();
-        if show_thinking {
-            let thinking = widget::container(widget::text::body("Thinking...").width(Length::Fill))
-                .class(theme::Container::Card)
-                .padding(spacing.space_s);
-            chat_column = chat_column.push(thinking);
+        if let Some(context_length) = self
+            .model_details
+            .get(self.active_model())
+            .and_then(|d| d.context_length)
+        {
+            row = row.push(widget::text::caption(format!(
+                "{}K ctx",
+                context_length / 1024
+            )));
         }
 
-        widget::scrollable(chat_column)
-            .height(Length::Fill)
-            .width(Length::Fill)
-            .into()
-    }
+        if self.token_usage.total() > 0 {
+            row = row.push(widget::text::caption(format!(
+                "{} tok",
+                self.token_usage.total()
+            )));
+        }
 
-    fn build_message_bubble<'a>(&'a self, role: &str, content: &'a str) -> Element<'a, Message> {
-        let spacing = theme::active().cosmic().spacing;
+        if let Some(server) = &self.server {
+            row = row.push(widget::text::caption(format!("Ollama {}", server.version)));
+        }
 
-        let (prefix, container_class) = if role == "user" {
-            ("You", theme::Container::Primary)
-        } else {
-            ("AI", theme::Container::Card)
-        };
+        let concise_btn =
+            widget::button::icon(widget::icon::from_name("format-justify-left-symbolic"))
+                .padding(spacing.space_xxs)
+                .selected(self.config.concise_mode)
+                .on_press(Message::ToggleConcise);
+        row = row.push(concise_btn);
 
-        let text_content = widget::text(content).width(Length::Fill);
-        let label = widget::text::caption(prefix);
+        // Only useful when static context is otherwise limited to the first turn
+        if self.config.static_context_once {
+            let refresh_btn =
+                widget::button::icon(widget::icon::from_name("view-refresh-symbolic"))
+                    .padding(spacing.space_xxs)
+                    .on_press(Message::RefreshContext);
+            row = row.push(refresh_btn);
+        }
 
-        let bubble_content = widget::column()
-            .spacing(spacing.space_xxs)
-            .push(label)
-            .push(text_content);
+        let row = row
+            .push(search_btn)
+            .push(settings_btn)
+            .push(import_btn)
+            .push(export_btn)
+            .push(clear_btn);
 
-        widget::container(bubble_content)
-            .class(container_class)
-            .padding(spacing.space_s)
+        // Conversation picker
+        let ids: Vec<u64> = self.conversations.iter().map(|c| c.id).collect();
+        let selected = ids.iter().position(|id| *id == self.conversation.id);
+        let conversation_picker =
+            widget::dropdown(&self.conversation_titles, selected, move |index| {
+                Message::SwitchConversation(ids.get(index).copied().unwrap_or_default())
+            })
+            .width(Length::Fill);
+        let new_btn = widget::button::icon(widget::icon::from_name("list-add-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::NewConversation);
+        let mut conversation_row = widget::row()
+            .align_y(Alignment::Center)
+            .spacing(spacing.space_xs)
+            .push(conversation_picker);
+        if !self.config.personas.is_empty() {
+            // A persona that was removed from the config reads as the default
+            let selected = self
+                .config
+                .personas
+                .iter()
+                .position(|p| p.name == self.conversation.persona)
+                .map_or(0, |i| i + 1);
+            conversation_row = conversation_row.push(widget::dropdown(
+                &self.persona_options,
+                Some(selected),
+                Message::SelectPersona,
+            ));
+        }
+        let mut conversation_row = conversation_row.push(new_btn);
+
+        let connection = match self.connection {
+            ConnectionStatus::Unknown => None,
+            ConnectionStatus::Connected => Some(("emblem-ok-symbolic", "Connected")),
+            ConnectionStatus::Disconnected(_) => Some(("dialog-error-symbolic", "Offline")),
+        };
+        if let Some((icon_name, label)) = connection {
+            conversation_row = conversation_row.push(
+                widget::row()
+                    .align_y(Alignment::Center)
+                    .spacing(spacing.space_xxxs)
+                    .push(widget::icon::from_name(icon_name).size(16).icon())
+                    .push(widget::text::caption(label)),
+            );
+        }
+
+        let mut header = widget::column()
+            .spacing(spacing.space_xs)
+            .push(conversation_row)
+            .push(row);
+
+        if self.manage_models && self.config.api_format == ApiFormat::Ollama {
+            header = header.push(self.build_model_management());
+        }
+
+        header.into()
+    }
+
+    /// Installed models with delete buttons, and a field for pulling a
+    /// model by name (or its progress while pulling).
+    fn build_model_management(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let mut column = widget::column().spacing(spacing.space_xxs);
+        for (index, model) in self.available_models.iter().enumerate() {
+            let active = model.name == self.active_model();
+            let mut row = widget::row()
+                .align_y(Alignment::Center)
+                .spacing(spacing.space_xs)
+                .push(widget::text::body(&model.name).width(Length::Fill));
+
+            if self.confirm_delete.as_ref() == Some(&model.name) {
+                row = row
+                    .push(widget::button::text("Cancel").on_press(Message::CancelDelete))
+                    .push(widget::button::destructive("Delete").on_press(Message::ConfirmDelete));
+            } else {
+                let mut delete_btn =
+                    widget::button::icon(widget::icon::from_name("user-trash-symbolic"))
+                        .padding(spacing.space_xxs);
+                if !active {
+                    delete_btn = delete_btn.on_press(Message::DeleteModel(index));
+                }
+                row = row.push(delete_btn);
+            }
+            column = column.push(row);
+        }
+
+        column.push(self.build_pull_row()).into()
+    }
+
+    /// Field for pulling a model by name, or its progress while pulling.
+    fn build_pull_row(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        if self.pull_rx.is_some() {
+            let (completed, total) = self.pull_progress;
+            let fraction = if total > 0 {
+                completed as f32 / total as f32
+            } else {
+                0.0
+            };
+            return widget::column()
+                .spacing(spacing.space_xxxs)
+                .push(widget::text::caption(format!(
+                    "Pulling {}... {:.0}%",
+                    self.pull_name,
+                    fraction * 100.0
+                )))
+                .push(progress_bar(0.0..=1.0, fraction))
+                .into();
+        }
+
+        let input = widget::text_input("Pull a model, e.g. llama3.2", &self.pull_name)
+            .on_input(Message::PullNameChanged)
+            .on_submit(|_| Message::PullModel)
+            .width(Length::Fill);
+        let pull_btn = widget::button::icon(widget::icon::from_name("go-down-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::PullModel);
+
+        widget::row()
+            .align_y(Alignment::Center)
+            .spacing(spacing.space_xs)
+            .push(input)
+            .push(pull_btn)
+            .into()
+    }
+
+    fn build_settings(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let mut settings = widget::column()
+            .spacing(spacing.space_s)
+            .push(widget::text::heading("Server"))
+            .push(widget::settings::item(
+                "URL",
+                widget::text_input(config::DEFAULT_OLLAMA_URL, &self.url_input)
+                    .on_input(Message::UrlChanged)
+                    .on_submit(|_| Message::SaveUrl),
+            ))
+            .push(self.build_connection_test())
+            .push(widget::settings::item(
+                "API format",
+                widget::dropdown(
+                    &API_FORMAT_LABELS[..],
+                    ApiFormat::ALL
+                        .iter()
+                        .position(|f| *f == self.config.api_format),
+                    Message::SetApiFormat,
+                ),
+            ))
+            .push(widget::settings::item(
+                "API key",
+                widget::secure_input(
+                    "None",
+                    &self.config.api_key,
+                    Some(Message::ToggleApiKeyVisible),
+                    !self.api_key_visible,
+                )
+                .on_input(Message::ApiKeyChanged)
+                .on_submit(|_| Message::SaveApiKey),
+            ))
+            .push(widget::settings::item(
+                "Stream responses",
+                widget::toggler(self.config.streaming).on_toggle(Message::SetStreaming),
+            ))
+            .push(widget::settings::item(
+                "Timeout (seconds)",
+                widget::text_input("120", &self.timeout_input)
+                    .on_input(Message::TimeoutChanged)
+                    .on_submit(|_| Message::SaveTimeout),
+            ))
+            .push(widget::text::heading("Model"))
+            .push(widget::settings::item(
+                "Default model",
+                widget::text_input(config::DEFAULT_MODEL, &self.model_input)
+                    .on_input(Message::ModelInputChanged)
+                    .on_submit(|_| Message::SaveModel),
+            ));
+
+        // OpenAI-compatible servers manage loading themselves and only
+        // speak chat
+        if self.config.api_format == ApiFormat::Ollama {
+            settings = settings
+                .push(widget::settings::item(
+                    "Keep model loaded",
+                    widget::dropdown(
+                        &KEEP_ALIVE_LABELS[..],
+                        // A hand-edited value matches no preset
+                        KEEP_ALIVE_VALUES
+                            .iter()
+                            .position(|v| *v == self.config.keep_alive.trim()),
+                        Message::SetKeepAlive,
+                    ),
+                ))
+                .push(widget::settings::item(
+                    "Raw completion",
+                    widget::toggler(self.config.raw_completion)
+                        .on_toggle(Message::SetRawCompletion),
+                ))
+                .push(widget::text::caption(
+                    "Sends each message as a bare prompt, without history or context, \
+                     for code-completion models",
+                ));
+        }
+
+        settings = settings.push(widget::settings::item(
+            "Web search",
+            widget::dropdown(
+                &SEARCH_MODE_LABELS[..],
+                SearchMode::ALL
+                    .iter()
+                    .position(|m| *m == self.config.search_mode),
+                Message::SetSearchMode,
+            ),
+        ));
+
+        // A message can still ask for a search when it's set to never, so
+        // the service stays configurable
+        settings = settings.push(widget::settings::item(
+            "Search service",
+            widget::dropdown(
+                &SearchBackend::LABELS[..],
+                Some(self.config.search_backend.index()),
+                Message::SetSearchBackend,
+            ),
+        ));
+        if matches!(self.config.search_backend, SearchBackend::SearXNG { .. }) {
+            settings = settings.push(widget::settings::item(
+                "SearXNG URL",
+                widget::text_input("http://localhost:8888", &self.searxng_url)
+                    .on_input(Message::SearxngUrlChanged)
+                    .on_submit(|_| Message::SaveSearxngUrl),
+            ));
+        }
+
+        settings
+            .push(widget::text::heading("Appearance"))
+            .push(widget::settings::item(
+                "Text size",
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(
+                        widget::button::icon(widget::icon::from_name("zoom-out-symbolic"))
+                            .on_press_maybe(
+                                (self.config.text_scale() > config::MIN_FONT_SCALE)
+                                    .then_some(Message::AdjustFontScale(-1)),
+                            ),
+                    )
+                    .push(widget::text::body(format!(
+                        "{:.0}%",
+                        self.config.text_scale() * 100.0
+                    )))
+                    .push(
+                        widget::button::icon(widget::icon::from_name("zoom-in-symbolic"))
+                            .on_press_maybe(
+                                (self.config.text_scale() < config::MAX_FONT_SCALE)
+                                    .then_some(Message::AdjustFontScale(1)),
+                            ),
+                    ),
+            ))
+            .push(popup_size_setting(
+                "Popup width",
+                self.config.popup_size().0,
+                config::MIN_POPUP_SIZE..=config::MAX_POPUP_WIDTH,
+                Message::SetPopupWidth,
+            ))
+            .push(popup_size_setting(
+                "Popup height",
+                self.config.popup_size().1,
+                config::MIN_POPUP_SIZE..=config::MAX_POPUP_HEIGHT,
+                Message::SetPopupHeight,
+            ))
+            .push(widget::settings::item(
+                "Accent color for your messages",
+                widget::toggler(self.config.user_bubble_accent)
+                    .on_toggle(Message::SetUserBubbleAccent),
+            ))
+            .push(widget::settings::item(
+                "Messages on both sides",
+                widget::toggler(self.config.align_bubbles).on_toggle(Message::SetAlignBubbles),
+            ))
+            .push(widget::settings::item(
+                "Wrap long lines in code",
+                widget::toggler(self.config.wrap_code).on_toggle(Message::SetWrapCode),
+            ))
+            .push(widget::settings::item(
+                "Show answers",
+                widget::dropdown(
+                    &REVEAL_MODE_LABELS[..],
+                    RevealMode::ALL
+                        .iter()
+                        .position(|m| *m == self.config.reveal_mode),
+                    Message::SetRevealMode,
+                ),
+            ))
+            .push(widget::settings::item(
+                "Enter sends (Shift+Enter for a new line)",
+                widget::toggler(self.config.enter_sends).on_toggle(Message::SetEnterSends),
+            ))
+            .push(widget::text::heading("Generation"))
+            .push(slider_setting(
+                "Temperature",
+                self.config.temperature,
+                0.0..=2.0,
+                Message::SetTemperature,
+            ))
+            .push(slider_setting(
+                "Top P",
+                self.config.top_p,
+                0.0..=1.0,
+                Message::SetTopP,
+            ))
+            .push(widget::settings::item(
+                "Context window",
+                widget::dropdown(
+                    &NUM_CTX_LABELS[..],
+                    NUM_CTX_VALUES
+                        .iter()
+                        .position(|n| *n == self.config.context_window()),
+                    Message::SetNumCtx,
+                ),
+            ))
+            .push(widget::text::caption(
+                "Larger windows remember more of the chat but use more VRAM (Ollama only)",
+            ))
+            .push(self.build_stop_sequences())
+            .push(widget::settings::item(
+                "Discard reasoning",
+                widget::toggler(self.config.strip_reasoning).on_toggle(Message::SetStripReasoning),
+            ))
+            .push(widget::text::heading("Context"))
+            .push(context_toggle(
+                "Clipboard",
+                ContextSource::Clipboard,
+                self.config.include_clipboard,
+            ))
+            .push(context_toggle(
+                "Selected text",
+                ContextSource::Selection,
+                self.config.include_selection,
+            ))
+            .push(context_toggle(
+                "System info",
+                ContextSource::SystemInfo,
+                self.config.include_system_info,
+            ))
+            .push(context_toggle(
+                "Recent errors",
+                ContextSource::RecentErrors,
+                self.config.include_recent_errors,
+            ))
+            .push(widget::text::heading("System prompt"))
+            .push(
+                text_editor(&self.prompt_editor)
+                    .on_action(Message::EditSystemPrompt)
+                    .height(Length::Fill),
+            )
+            .push(
+                widget::row()
+                    .spacing(spacing.space_xs)
+                    .push(
+                        widget::button::text("Reset to default")
+                            .on_press(Message::ResetSystemPrompt),
+                    )
+                    .push(widget::button::suggested("Save").on_press(Message::SaveSystemPrompt)),
+            )
+            .height(Length::Fill)
+            .into()
+    }
+
+    /// Stop sequences with remove buttons, and a field for adding one.
+    fn build_stop_sequences(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let mut column = widget::column()
+            .spacing(spacing.space_xxs)
+            .push(widget::settings::item(
+                "Stop sequences",
+                widget::text_input("Add, e.g. \\n\\n", &self.stop_input)
+                    .on_input(Message::StopInputChanged)
+                    .on_submit(|_| Message::AddStopSequence),
+            ));
+
+        for (index, stop) in self.config.stop_sequences.iter().enumerate() {
+            let remove_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                .padding(spacing.space_xxxs)
+                .on_press(Message::RemoveStopSequence(index));
+            // Quoted and escaped so whitespace is visible
+            column = column.push(
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(
+                        widget::text::caption(format!("{:?}", stop))
+                            .font(cosmic::font::mono())
+                            .width(Length::Fill),
+                    )
+                    .push(remove_btn),
+            );
+        }
+
+        column.into()
+    }
+
+    /// "Test connection" button with the outcome of the last test.
+    fn build_connection_test(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let mut row = widget::row()
+            .spacing(spacing.space_xs)
+            .align_y(Alignment::Center)
+            .push(widget::button::text("Test connection").on_press(Message::TestConnection));
+
+        let result = match &self.connection_test {
+            Some(Ok(1)) => Some(("emblem-ok-symbolic", "Connected, 1 model".to_string())),
+            Some(Ok(count)) => Some(("emblem-ok-symbolic", format!("Connected, {} models", count))),
+            Some(Err(err)) => Some(("dialog-error-symbolic", err.clone())),
+            None => None,
+        };
+        if let Some((icon_name, text)) = result {
+            row = row
+                .push(widget::icon::from_name(icon_name).size(16).icon())
+                .push(widget::text::caption(text).width(Length::Fill));
+        }
+
+        row.into()
+    }
+
+    /// Read-only details of the active model from `/api/show`.
+    fn build_model_info(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+        let name = self.active_model();
+
+        let mut info = widget::column()
+            .spacing(spacing.space_xs)
+            .push(widget::text::title4(name));
+
+        if let Some(model) = self.available_models.iter().find(|m| m.name == name) {
+            let summary = [
+                &model.family,
+                &model.parameter_size,
+                &model.quantization,
+                &model.display_size,
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" · ");
+            info = info.push(widget::text::caption(summary));
+        }
+
+        let Some(details) = self.model_details.get(name) else {
+            return info
+                .push(widget::text::body("Loading model info..."))
+                .into();
+        };
+
+        if !details.capabilities.is_empty() {
+            info = info.push(widget::text::body(format!(
+                "Capabilities: {}",
+                details.capabilities.join(", ")
+            )));
+        }
+        if let Some(context_length) = details.context_length {
+            info = info.push(widget::text::body(format!(
+                "Context window: {} tokens",
+                context_length
+            )));
+        }
+
+        // Older servers and imported models leave some of these out
+        let sections = [
+            ("Parameters", &details.parameters),
+            ("System prompt", &details.system),
+            ("Template", &details.template),
+            ("License", &details.license),
+            ("Modelfile", &details.modelfile),
+        ];
+        for (label, value) in sections {
+            if value.trim().is_empty() {
+                continue;
+            }
+            info = info.push(widget::text::heading(label)).push(
+                widget::text::body(value.trim())
+                    .font(cosmic::font::mono())
+                    .width(Length::Fill),
+            );
+        }
+
+        widget::scrollable(info).height(Length::Fill).into()
+    }
+
+    fn build_status<'a>(&self, status: &'a Status) -> Element<'a, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let icon_name = if status.is_error {
+            "dialog-error-symbolic"
+        } else {
+            "emblem-ok-symbolic"
+        };
+
+        widget::row()
+            .align_y(Alignment::Center)
+            .spacing(spacing.space_xxs)
+            .push(widget::icon::from_name(icon_name).size(16).icon())
+            .push(widget::text::caption(&status.text).width(Length::Fill))
+            .into()
+    }
+
+    /// Why the server can't be reached, with a way to try again.
+    fn build_offline_banner<'a>(&'a self, reason: &'a str) -> Element<'a, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let text = widget::column()
+            .width(Length::Fill)
+            .push(widget::text::body(format!(
+                "Can't reach {}",
+                self.config.ollama_url
+            )))
+            .push(widget::text::caption(reason));
+        let retry_btn = widget::button::text("Retry").on_press(Message::RetryConnection);
+        let close_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::DismissOffline);
+
+        widget::container(
+            widget::row()
+                .align_y(Alignment::Center)
+                .spacing(spacing.space_xs)
+                .push(
+                    widget::icon::from_name("network-offline-symbolic")
+                        .size(16)
+                        .icon(),
+                )
+                .push(text)
+                .push(retry_btn)
+                .push(close_btn),
+        )
+        .padding(spacing.space_xs)
+        .class(theme::Container::Card)
+        .into()
+    }
+
+    /// Details of the last failed request, set apart from the chat.
+    fn build_last_error<'a>(&self, error: &'a LastError) -> Element<'a, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let report = error.report();
+        let copy_btn = widget::button::icon(widget::icon::from_name("edit-copy-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::CopyText(report.clone()));
+        let close_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::DismissLastError);
+        let header = widget::row()
+            .align_y(Alignment::Center)
+            .spacing(spacing.space_xs)
+            .push(
+                widget::icon::from_name("dialog-error-symbolic")
+                    .size(16)
+                    .icon(),
+            )
+            .push(widget::text::body("Last error").width(Length::Fill))
+            .push(copy_btn)
+            .push(close_btn);
+
+        widget::container(
+            widget::column()
+                .spacing(spacing.space_xxs)
+                .push(header)
+                .push(
+                    widget::text(report)
+                        .size(self.caption_size())
+                        .font(cosmic::font::mono())
+                        .wrapping(Wrapping::WordOrGlyph),
+                ),
+        )
+        .padding(spacing.space_xs)
+        .class(theme::Container::Card)
+        .into()
+    }
+
+    /// Asks whether an imported conversation joins the current one.
+    fn build_import_prompt<'a>(&'a self, imported: &'a ChatHistory) -> Element<'a, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let title = if imported.title.is_empty() {
+            history::conversation_title(&imported.messages)
+        } else {
+            imported.title.clone()
+        };
+        let text = widget::column()
+            .width(Length::Fill)
+            .push(widget::text::body(format!("Import \"{}\"", title)))
+            .push(widget::text::caption(format!(
+                "{} messages",
+                imported.messages.len()
+            )));
+        let merge_btn = widget::button::text("Add here").on_press(Message::ApplyImport(true));
+        let separate_btn =
+            widget::button::suggested("New conversation").on_press(Message::ApplyImport(false));
+        let close_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::CancelImport);
+
+        widget::container(
+            widget::row()
+                .align_y(Alignment::Center)
+                .spacing(spacing.space_xs)
+                .push(text)
+                .push(merge_btn)
+                .push(separate_btn)
+                .push(close_btn),
+        )
+        .padding(spacing.space_xs)
+        .class(theme::Container::Card)
+        .into()
+    }
+
+    fn build_search_row(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let input = widget::text_input("Find in conversation", &self.search_query)
+            .id(SEARCH_INPUT_ID.clone())
+            .on_input(Message::SearchChanged)
+            .width(Length::Fill);
+
+        let mut row = widget::row()
+            .spacing(spacing.space_xs)
+            .align_y(Alignment::Center)
+            .push(input);
+
+        if let Some(query) = self.search_filter() {
+            let count = self
+                .messages
+                .iter()
+                .filter(|m| matches_search(&m.content, &query))
+                .count();
+            let summary = match count {
+                0 => "No matches".to_string(),
+                1 => "1 match".to_string(),
+                n => format!("{} matches", n),
+            };
+            row = row.push(widget::text::caption(summary));
+        }
+
+        row.into()
+    }
+
+    /// Lowercased search text, if the conversation is being filtered.
+    fn search_filter(&self) -> Option<String> {
+        let query = self.search_query.trim();
+        (self.show_search && !query.is_empty()).then(|| query.to_lowercase())
+    }
+
+    /// Whether a message is shown under the current search. The bubble
+    /// being streamed into always is, so the response can be followed.
+    fn shows_message(&self, index: usize, filter: Option<&str>) -> bool {
+        let streaming = index + 1 == self.messages.len() && self.is_streaming();
+        filter.is_none_or(|query| streaming || matches_search(&self.messages[index].content, query))
+    }
+
+    fn build_chat_content(&self) -> Element<'_, Message> {
+        if self.config.plain_text_mode {
+            return self.build_plain_transcript();
+        }
+
+        let spacing = theme::active().cosmic().spacing;
+        let mut chat_column = widget::column().spacing(spacing.space_xs);
+
+        // Only bubbles in or near view are built; space of their estimated
+        // height stands in for the rest
+        let filter = self.search_filter();
+        let heights = self.message_heights();
+        let window = self.built_window(&heights);
+        if let Some(space) = chat_spacer(&heights[..window.start]) {
+            chat_column = chat_column.push(space);
+        }
+
+        let last_index = self.messages.len().saturating_sub(1);
+        for index in window.clone() {
+            if !self.shows_message(index, filter.as_deref()) {
+                continue;
+            }
+            let is_last = index == last_index;
+            let message_widget = self.build_message_bubble(index, &self.messages[index], is_last);
+            chat_column = chat_column.push(message_widget);
+        }
+
+        if let Some(space) = chat_spacer(&heights[window.end..]) {
+            chat_column = chat_column.push(space);
+        }
+
+        // Before the stream starts the placeholder gets its own card; once
+        // StreamReady pushes the empty bubble the bubble shows it instead
+        if self.awaiting_stream() && window.end == self.messages.len() {
+            let thinking = widget::container(
+                widget::text(self.typing_indicator())
+                    .size(self.text_size())
+                    .width(Length::Fill),
+            )
+            .class(theme::Container::Card)
+            .padding(spacing.space_s);
+            chat_column = chat_column.push(thinking);
+        }
+
+        widget::scrollable(chat_column)
+            .id(CHAT_SCROLL_ID.clone())
+            .on_scroll(Message::ChatScrolled)
+            .height(Length::Fill)
             .width(Length::Fill)
             .into()
     }
 
-    fn build_input_row(&self) -> Element<'_, Message> {
+    /// Keep the chat pinned to the bottom unless the user scrolled up.
+    fn follow_chat(&self) -> Task<cosmic::Action<Message>> {
+        if self.chat_scrolled_up {
+            return Task::none();
+        }
+        scrollable::snap_to(CHAT_SCROLL_ID.clone(), scrollable::RelativeOffset::END)
+    }
+
+    /// Estimated height of each message in the chat view. Messages the
+    /// search hides take none.
+    fn message_heights(&self) -> Vec<f32> {
+        let width = self
+            .chat_viewport
+            .map_or(CHAT_FALLBACK_WIDTH, |viewport| viewport.bounds().width);
+        let spacing = theme::active().cosmic().spacing;
+        let text_size = self.text_size();
+        // The label, padding and spacing around a bubble
+        let chrome = if self.config.plain_text_mode {
+            f32::from(spacing.space_s)
+        } else {
+            2.0 * text_size + 2.0 * f32::from(spacing.space_s) + f32::from(spacing.space_xs)
+        };
+
+        let filter = self.search_filter();
+        self.messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                if !self.shows_message(index, filter.as_deref()) {
+                    return 0.0;
+                }
+                let images = if message.images.is_empty() || self.config.plain_text_mode {
+                    0.0
+                } else {
+                    THUMBNAIL_SIZE
+                };
+                text_height(&message.content, width, text_size) + chrome + images
+            })
+            .collect()
+    }
+
+    /// The messages to build into the chat view: the stored window while
+    /// scrolled up, otherwise enough of the newest to fill the view.
+    fn built_window(&self, heights: &[f32]) -> Range<usize> {
+        match &self.chat_window {
+            Some(window) if self.chat_scrolled_up => {
+                let end = window.end.min(heights.len());
+                window.start.min(end)..end
+            }
+            _ => {
+                let view_height = self
+                    .chat_viewport
+                    .map_or(CHAT_FALLBACK_HEIGHT, |viewport| viewport.bounds().height);
+                newest_window(heights, (1.0 + 2.0 * CHAT_BUFFER_VIEWS) * view_height)
+            }
+        }
+    }
+
+    /// Unstyled transcript: one role-prefixed text block per message, no
+    /// containers, so screen readers read it in order without extra chrome.
+    fn build_plain_transcript(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+        let mut transcript = widget::column().spacing(spacing.space_s);
+
+        let filter = self.search_filter();
+        let heights = self.message_heights();
+        let window = self.built_window(&heights);
+        if let Some(space) = chat_spacer(&heights[..window.start]) {
+            transcript = transcript.push(space);
+        }
+
+        for index in window.clone() {
+            let message = &self.messages[index];
+            if !self.shows_message(index, filter.as_deref()) {
+                continue;
+            }
+            let text = if message.role == "user" {
+                format!("You: {}", message.content)
+            } else {
+                format!("AI: {}", markdown::split_thinking(&message.content).answer)
+            };
+            transcript = transcript.push(
+                widget::text(text)
+                    .size(self.text_size())
+                    .width(Length::Fill),
+            );
+        }
+
+        if let Some(space) = chat_spacer(&heights[window.end..]) {
+            transcript = transcript.push(space);
+        }
+
+        if self.awaiting_stream() && window.end == self.messages.len() {
+            transcript = transcript.push(
+                widget::text(format!("AI: {}", self.config.thinking_placeholder))
+                    .size(self.text_size())
+                    .width(Length::Fill),
+            );
+        }
+
+        widget::scrollable(transcript)
+            .id(CHAT_SCROLL_ID.clone())
+            .on_scroll(Message::ChatScrolled)
+            .height(Length::Fill)
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn build_message_bubble<'a>(
+        &'a self,
+        index: usize,
+        message: &'a HistoryMessage,
+        is_last: bool,
+    ) -> Element<'a, Message> {
         let spacing = theme::active().cosmic().spacing;
+        let role = message.role.as_str();
+        let content = message.content.as_str();
+        // Only the last assistant bubble can be receiving chunks
+        let streaming = is_last && self.is_streaming();
+
+        let (prefix, container_class) = if role == "user" && self.config.user_bubble_accent {
+            ("You", theme::Container::Primary)
+        } else if role == "user" {
+            ("You", theme::Container::Card)
+        } else {
+            ("AI", theme::Container::Card)
+        };
+
+        // Reasoning goes in its own collapsible section
+        let reply = (role != "user").then(|| markdown::split_thinking(content));
+        let answer = reply
+            .as_ref()
+            .map_or(content, |reply| reply.answer.as_str());
+
+        // A trailing cursor marks the bubble that is still being written;
+        // until the first answer token arrives it holds the placeholder
+        let text_content: Element<'a, Message> = if streaming && answer.is_empty() {
+            widget::text(self.typing_indicator())
+                .size(self.text_size())
+                .width(Length::Fill)
+                .into()
+        } else if role == "user" && !message.images.is_empty() {
+            let thumbnails = message
+                .images
+                .iter()
+                .filter_map(|name| self.thumbnails.get(name))
+                .fold(widget::row().spacing(spacing.space_xxs), |row, handle| {
+                    row.push(
+                        widget::image(handle.clone())
+                            .width(Length::Fixed(THUMBNAIL_SIZE))
+                            .height(Length::Fixed(THUMBNAIL_SIZE)),
+                    )
+                });
+            widget::column()
+                .spacing(spacing.space_xxs)
+                .push(thumbnails)
+                .push(
+                    widget::text(content)
+                        .size(self.text_size())
+                        .width(Length::Fill),
+                )
+                .into()
+        } else if role == "user" {
+            widget::text(content)
+                .size(self.text_size())
+                .width(Length::Fill)
+                .into()
+        } else if streaming {
+            // Kept out of the markdown so it can't end up inside a code
+            // block or break an unfinished table
+            widget::column()
+                .push(render_markdown(
+                    answer,
+                    self.copied.as_deref(),
+                    self.text_size(),
+                    self.config.wrap_code,
+                ))
+                .push(widget::text("▍").size(self.text_size()))
+                .into()
+        } else {
+            render_markdown(
+                answer,
+                self.copied.as_deref(),
+                self.text_size(),
+                self.config.wrap_code,
+            )
+        };
+        let mut caption = prefix.to_string();
+        if !message.model.is_empty() {
+            caption = format!("{} · {}", caption, message.model);
+        }
+        if let Some(sent) = history::relative_time(message.timestamp) {
+            caption = format!("{} · {}", caption, sent);
+        }
+        let mut label = widget::row()
+            .align_y(Alignment::Center)
+            .push(widget::text::caption(caption).width(Length::Fill));
+
+        // The latest answer (or error) can be regenerated once it's done
+        if is_last
+            && role == "assistant"
+            && !self.waiting
+            && self.messages.iter().any(|m| m.role == "user")
+        {
+            label = label.push(
+                widget::button::icon(widget::icon::from_name("view-refresh-symbolic"))
+                    .padding(spacing.space_xxxs)
+                    .on_press(Message::Regenerate),
+            );
+            if self.available_models.len() > 1 {
+                label = label.push(
+                    widget::button::icon(widget::icon::from_name("view-more-symbolic"))
+                        .padding(spacing.space_xxxs)
+                        .selected(self.choosing_regenerate_model)
+                        .on_press(Message::ToggleRegenerateModels),
+                );
+            }
+        }
+
+        // A missing model can be pulled right from the error
+        if is_last
+            && role == "assistant"
+            && !self.waiting
+            && self.pull_rx.is_none()
+            && self.config.api_format == ApiFormat::Ollama
+            && let Some(model) = &self.missing_model
+        {
+            label = label.push(
+                widget::button::text(format!("Pull {}", model))
+                    .leading_icon(widget::icon::from_name("folder-download-symbolic"))
+                    .on_press(Message::PullMissingModel),
+            );
+        }
+
+        // Answers can be copied whole, as the markdown the model wrote;
+        // the greeting before the first question is no answer
+        if role == "assistant"
+            && !streaming
+            && !answer.is_empty()
+            && self.messages[..index].iter().any(|m| m.role == "user")
+        {
+            let copy_icon = if self.copied.as_deref() == Some(answer) {
+                "object-select-symbolic"
+            } else {
+                "edit-copy-symbolic"
+            };
+            label = label.push(
+                widget::button::icon(widget::icon::from_name(copy_icon))
+                    .padding(spacing.space_xxxs)
+                    .on_press(Message::CopyText(answer.to_string())),
+            );
+        }
+
+        // Sent messages can be edited, which forks the chat from there
+        if role == "user" && !self.waiting {
+            label = label.push(
+                widget::button::icon(widget::icon::from_name("document-edit-symbolic"))
+                    .padding(spacing.space_xxxs)
+                    .on_press(Message::EditMessage(index)),
+            );
+        }
+
+        let mut bubble_content = widget::column().spacing(spacing.space_xxs).push(label);
 
-        let input = widget::text_input("Type a message...", &self.input_text)
-            .on_input(Message::InputChanged)
-            .on_submit(|_| Message::Submit)
+        if is_last && role == "assistant" && !self.waiting && self.choosing_regenerate_model {
+            // Only installed models; a trailing "not installed" entry has
+            // no model to regenerate with
+            let installed = self
+                .model_options
+                .get(..self.available_models.len())
+                .unwrap_or_default();
+            bubble_content = bubble_content.push(
+                widget::row()
+                    .align_y(Alignment::Center)
+                    .spacing(spacing.space_xs)
+                    .push(widget::text::caption("Regenerate with"))
+                    .push(
+                        widget::dropdown(installed, None, Message::RegenerateWith)
+                            .width(Length::Fill),
+                    ),
+            );
+        }
+
+        if let Some(reply) = reply.filter(|reply| !reply.thoughts.is_empty()) {
+            let (icon, caption) = match (self.show_thoughts, reply.thinking) {
+                (true, _) => ("go-down-symbolic", "Thoughts"),
+                (false, true) => ("go-next-symbolic", "Thinking…"),
+                (false, false) => ("go-next-symbolic", "Thoughts"),
+            };
+            let toggle = widget::button::text(caption)
+                .leading_icon(widget::icon::from_name(icon))
+                .on_press(Message::ToggleThoughts);
+            bubble_content = bubble_content.push(toggle);
+            if self.show_thoughts {
+                bubble_content = bubble_content.push(
+                    widget::container(widget::text::caption(reply.thoughts).width(Length::Fill))
+                        .padding([0, spacing.space_s]),
+                );
+            }
+        }
+
+        let mut bubble_content = bubble_content.push(text_content);
+
+        if !streaming && let Some(stats) = message.stats {
+            bubble_content = bubble_content.push(widget::text::caption(format!(
+                "{} tokens · {:.0} tok/s",
+                stats.tokens, stats.tokens_per_sec
+            )));
+        }
+
+        let bubble = widget::container(bubble_content)
+            .class(container_class)
+            .padding(spacing.space_s)
             .width(Length::Fill);
 
+        if !self.config.align_bubbles {
+            return bubble.into();
+        }
+
+        // A share of the width rather than a fixed size, so long content
+        // still wraps inside the narrow popup
+        let gap = widget::Space::with_width(Length::FillPortion(BUBBLE_GAP_PORTION));
+        let bubble = widget::container(bubble).width(Length::FillPortion(BUBBLE_PORTION));
+        if role == "user" {
+            widget::row().push(gap).push(bubble).into()
+        } else {
+            widget::row().push(bubble).push(gap).into()
+        }
+    }
+
+    fn build_input_row(&self) -> Element<'_, Message> {
+        let spacing = theme::active().cosmic().spacing;
+
+        let placeholder = if !self.has_model() {
+            "Select a model first"
+        } else if self.waiting && self.config.queue_submissions {
+            "Queue a follow-up..."
+        } else {
+            "Type a message..."
+        };
+
+        // Enter sends or breaks the line depending on the setting; the
+        // arrow keys recall prompts while the input is empty or recalled
+        let enter_sends = self.config.enter_sends;
+        let recalling = self.input_text.is_empty() || self.prompt_history_index.is_some();
+        let input = text_editor(&self.input_editor)
+            .placeholder(placeholder)
+            .size(self.text_size())
+            .max_height(INPUT_MAX_HEIGHT)
+            .on_action(Message::EditInput)
+            .key_binding(move |key_press| {
+                if key_press.status != text_editor::Status::Focused {
+                    return text_editor::Binding::from_key_press(key_press);
+                }
+                let modifiers = key_press.modifiers;
+                match &key_press.key {
+                    keyboard::Key::Named(keyboard::key::Named::Enter) => {
+                        let send = if enter_sends {
+                            !modifiers.shift()
+                        } else {
+                            modifiers.control()
+                        };
+                        Some(if send {
+                            text_editor::Binding::Custom(Message::Submit)
+                        } else {
+                            text_editor::Binding::Enter
+                        })
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::ArrowUp) if recalling => {
+                        Some(text_editor::Binding::Custom(Message::RecallPrevious))
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::ArrowDown) if recalling => {
+                        Some(text_editor::Binding::Custom(Message::RecallNext))
+                    }
+                    _ => text_editor::Binding::from_key_press(key_press),
+                }
+            });
+
         let send_btn = if self.waiting {
-            widget::button::icon(widget::icon::from_name("process-working-symbolic"))
+            widget::button::icon(widget::icon::from_name("media-playback-stop-symbolic"))
                 .padding(spacing.space_xxs)
+                .on_press(Message::CancelStream)
         } else {
+            // Nothing to send to until a model is picked
             widget::button::icon(widget::icon::from_name("go-next-symbolic"))
                 .padding(spacing.space_xxs)
-                .on_press(Message::Submit)
+                .on_press_maybe(self.has_model().then_some(Message::Submit))
         };
 
-        widget::row()
+        // Per-message override of the web search heuristic
+        let search_icon = match self.next_search_mode() {
+            SearchMode::Heuristic => "system-search-symbolic",
+            SearchMode::Always => "web-browser-symbolic",
+            SearchMode::Never => "network-offline-symbolic",
+        };
+        let search_btn = widget::button::icon(widget::icon::from_name(search_icon))
+            .padding(spacing.space_xxs)
+            .selected(self.search_mode.is_some())
+            .on_press(Message::CycleSearchMode);
+
+        let attach_btn = widget::button::icon(widget::icon::from_name("mail-attachment-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press(Message::AttachFile);
+
+        // Models without the vision capability would ignore the image
+        let image_btn = widget::button::icon(widget::icon::from_name("image-x-generic-symbolic"))
+            .padding(spacing.space_xxs)
+            .on_press_maybe(self.accepts_images().then_some(Message::AttachImage));
+
+        let mut input_row = widget::row()
             .spacing(spacing.space_xs)
             .align_y(Alignment::Center)
-            .push(input)
-            .push(send_btn)
-            .into()
+            .push(attach_btn)
+            .push(image_btn);
+        if self.config.include_clipboard {
+            input_row = input_row.push(
+                widget::button::icon(widget::icon::from_name("edit-paste-symbolic"))
+                    .padding(spacing.space_xxs)
+                    .selected(self.attached_clipboard.is_some())
+                    .on_press(Message::AttachText(ContextSource::Clipboard)),
+            );
+        }
+        if self.config.include_selection {
+            input_row = input_row.push(
+                widget::button::icon(widget::icon::from_name("edit-select-all-symbolic"))
+                    .padding(spacing.space_xxs)
+                    .selected(self.attached_selection.is_some())
+                    .on_press(Message::AttachText(ContextSource::Selection)),
+            );
+        }
+        let input_row = input_row.push(input).push(search_btn).push(send_btn);
+
+        let mut column = widget::column().spacing(spacing.space_xxs);
+
+        if let Some(attachment) = &self.attachment {
+            let label = if attachment.truncated {
+                format!("File: {} (truncated)", attachment.name)
+            } else {
+                format!("File: {}", attachment.name)
+            };
+            let remove_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                .padding(spacing.space_xxxs)
+                .on_press(Message::RemoveAttachment);
+            column = column.push(
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(widget::text::caption(label).width(Length::Fill))
+                    .push(remove_btn),
+            );
+        }
+
+        let captured = [
+            (
+                "Clipboard",
+                ContextSource::Clipboard,
+                &self.attached_clipboard,
+            ),
+            (
+                "Selection",
+                ContextSource::Selection,
+                &self.attached_selection,
+            ),
+        ];
+        for (name, source, text) in captured {
+            let Some(text) = text else {
+                continue;
+            };
+            let remove_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                .padding(spacing.space_xxxs)
+                .on_press(Message::RemoveText(source));
+            column = column.push(
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(
+                        widget::text::caption(format!("{}: {}", name, preview(text)))
+                            .width(Length::Fill),
+                    )
+                    .push(remove_btn),
+            );
+        }
+
+        if let Some(image) = &self.image {
+            let remove_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                .padding(spacing.space_xxxs)
+                .on_press(Message::RemoveImage);
+            column = column.push(
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(
+                        widget::text::caption(format!("Image: {}", image.name)).width(Length::Fill),
+                    )
+                    .push(remove_btn),
+            );
+        }
+
+        // What the model saw alongside the last message
+        if let Some(sources) = &self.attached_sources {
+            let summary = if sources.is_empty() {
+                "No context attached".to_string()
+            } else {
+                format!("Attached: {}", sources.join(", "))
+            };
+            column = column.push(widget::text::caption(summary));
+        }
+
+        // Show pending follow-ups above the input so they can be dropped
+        for (index, text) in self.queued.iter().enumerate() {
+            let remove_btn = widget::button::icon(widget::icon::from_name("window-close-symbolic"))
+                .padding(spacing.space_xxxs)
+                .on_press(Message::RemoveQueued(index));
+            column = column.push(
+                widget::row()
+                    .spacing(spacing.space_xxs)
+                    .align_y(Alignment::Center)
+                    .push(widget::text::caption(format!("Queued: {}", text)).width(Length::Fill))
+                    .push(remove_btn),
+            );
+        }
+
+        let column = column.push(input_row);
+        match self.build_input_counter() {
+            Some(counter) => column.push(counter).into(),
+            None => column.into(),
+        }
+    }
+
+    /// Live size of the prompt being typed, with the attachments that go
+    /// along with it; `None` while there is nothing to count.
+    fn build_input_counter(&self) -> Option<Element<'_, Message>> {
+        let attached: usize = [
+            self.attachment.as_ref().map(|a| a.contents.chars().count()),
+            self.attached_clipboard.as_ref().map(|t| t.chars().count()),
+            self.attached_selection.as_ref().map(|t| t.chars().count()),
+        ]
+        .into_iter()
+        .flatten()
+        .sum();
+        if self.input_text.is_empty() && attached == 0 {
+            return None;
+        }
+
+        let chars = self.input_text.chars().count();
+        let words = self.input_text.split_whitespace().count();
+        let tokens = chars.div_ceil(ollama::CHARS_PER_TOKEN);
+        let mut text = format!("{} words · {} chars · ~{} tokens", words, chars, tokens);
+        let total = (chars + attached).div_ceil(ollama::CHARS_PER_TOKEN);
+        if attached > 0 {
+            text.push_str(&format!(" · ~{} with attachments", total));
+        }
+
+        // Warn before the model would have to drop part of the prompt
+        let window = self.config.context_window().map(u64::from).or_else(|| {
+            self.model_details
+                .get(self.active_model())
+                .and_then(|d| d.context_length)
+        });
+        Some(match window {
+            Some(window) if total as u64 > window => widget::row()
+                .align_y(Alignment::Center)
+                .spacing(theme::active().cosmic().spacing.space_xxs)
+                .push(
+                    widget::icon::from_name("dialog-warning-symbolic")
+                        .size(16)
+                        .icon(),
+                )
+                .push(widget::text::caption(format!(
+                    "{} · over the {} token context",
+                    text, window
+                )))
+                .into(),
+            _ => widget::text::caption(text).into(),
+        })
+    }
+
+    /// Replace the message input's text.
+    fn set_input(&mut self, text: String) {
+        self.input_editor = text_editor::Content::with_text(&text);
+        self.input_text = text;
+    }
+
+    /// Empty the message input, returning what was typed.
+    fn take_input(&mut self) -> String {
+        self.input_editor = text_editor::Content::new();
+        std::mem::take(&mut self.input_text)
     }
 
     fn handle_submit(&mut self) -> Task<cosmic::Action<Message>> {
-        if self.input_text.trim().is_empty() || self.waiting {
+        if self.input_text.trim().is_empty() {
             return Task::none();
         }
 
-        let query = self.input_text.clone();
-        self.messages.push(("user".to_string(), query.clone()));
-        self.input_text.clear();
-        self.waiting = true;
+        // The server would only answer with an opaque error
+        if !self.has_model() {
+            return self.show_status("Select a model first".to_string(), true);
+        }
 
-        let url = self.config.ollama_url.clone();
-        let model = self.config.model.clone();
-        let messages = self.messages.clone();
+        // The image may have been attached before switching models
+        if self.image.is_some() && !self.accepts_images() {
+            return self.show_status(format!("{} can't read images", self.active_model()), true);
+        }
 
-        Task::perform(
-            async move { start_ollama_stream(url, model, messages, query).await },
-            |rx| cosmic::Action::App(Message::StreamReady(Arc::new(Mutex::new(rx)))),
-        )
+        if self.waiting {
+            if self.config.queue_submissions {
+                let query = self.take_input();
+                self.queued.push(query);
+            }
+            return Task::none();
+        }
+
+        let query = self.take_input();
+        self.prompt_history_index = None;
+        self.prompt_history.push(query.clone());
+        if self.prompt_history.len() > PROMPT_HISTORY_SIZE {
+            self.prompt_history.remove(0);
+        }
+        self.send_query(query)
+    }
+
+    /// Search mode for the next message: the override if one is set, else
+    /// the configured mode.
+    fn next_search_mode(&self) -> SearchMode {
+        self.search_mode.unwrap_or(self.config.search_mode)
+    }
+
+    /// Model for the active conversation, falling back to the configured
+    /// default for conversations that haven't picked one.
+    fn active_model(&self) -> &str {
+        if self.conversation.model.is_empty() {
+            &self.config.model
+        } else {
+            &self.conversation.model
+        }
+    }
+
+    /// Whether the active model can read images. Models whose details
+    /// aren't known (yet, or on OpenAI-compatible servers) get the benefit
+    /// of the doubt.
+    fn accepts_images(&self) -> bool {
+        self.model_details
+            .get(self.active_model())
+            .is_none_or(ModelShow::supports_vision)
+    }
+
+    /// Whether a model is configured to send messages to.
+    fn has_model(&self) -> bool {
+        !self.active_model().trim().is_empty()
+    }
+
+    /// Whether the last assistant message is currently receiving chunks.
+    fn is_streaming(&self) -> bool {
+        self.waiting
+            && self.stream_rx.is_some()
+            && self.messages.last().is_some_and(|m| m.role == "assistant")
+    }
+
+    /// Whether a request is in flight but its response bubble doesn't exist
+    /// yet. Mutually exclusive with `is_streaming` so only one placeholder
+    /// is ever shown.
+    fn awaiting_stream(&self) -> bool {
+        self.waiting && !self.is_streaming()
+    }
+
+    /// Whether a response is on its way but none of its answer is visible
+    /// yet (reasoning doesn't count).
+    fn awaiting_first_token(&self) -> bool {
+        self.awaiting_stream()
+            || (self.is_streaming()
+                && self
+                    .messages
+                    .last()
+                    .is_some_and(|m| markdown::split_thinking(&m.content).answer.is_empty()))
+    }
+
+    /// Size of chat and input text at the configured scale.
+    fn text_size(&self) -> f32 {
+        BASE_TEXT_SIZE * self.config.text_scale()
+    }
+
+    /// Size of small print at the configured scale.
+    fn caption_size(&self) -> f32 {
+        BASE_CAPTION_SIZE * self.config.text_scale()
+    }
+
+    /// The thinking placeholder with one to three dots cycling after it,
+    /// or a note that the model is loading once nothing has arrived for a
+    /// while.
+    fn typing_indicator(&self) -> String {
+        let text = if self.loading_model() {
+            "Loading model into memory"
+        } else {
+            self.config
+                .thinking_placeholder
+                .trim_end_matches(['.', '…'])
+        };
+        format!("{}{}", text, ".".repeat(self.typing_frame % 3 + 1))
+    }
+
+    /// Whether the server hasn't sent anything, not even reasoning, for
+    /// long enough that it's most likely loading the model.
+    ///
+    /// Ollama reports the load time only once the answer is done, so this
+    /// goes by how long the first chunk is taking.
+    fn loading_model(&self) -> bool {
+        let nothing_yet = self.awaiting_stream()
+            || (self.is_streaming()
+                && self.pending_reveal.is_empty()
+                && self.messages.last().is_some_and(|m| m.content.is_empty()));
+        nothing_yet
+            && self
+                .turn_started
+                .is_some_and(|started| started.elapsed() >= MODEL_LOAD_HINT_DELAY)
+    }
+
+    /// Persist the conversation and its token counter, titling it after
+    /// its first user message.
+    fn save_history(&mut self) {
+        self.conversation.draft = self.draft().to_string();
+        if self.conversation.title.is_empty() {
+            self.conversation.title = history::conversation_title(&self.messages);
+        }
+        // Pin the default so later changes to it don't affect this chat
+        if self.conversation.model.is_empty() {
+            self.conversation.model = self.config.model.clone();
+        }
+        let _ = history::save_history(
+            &self.conversation,
+            &self.messages,
+            self.token_usage,
+            (!self.config.full_scrollback).then(|| self.config.history_limit()),
+        );
+        self.update_conversation_entry();
+    }
+
+    /// Add streamed text to the answer being written.
+    fn reveal(&mut self, text: &str) {
+        if let Some(message) = self.messages.last_mut()
+            && message.role == "assistant"
+        {
+            message.content.push_str(text);
+        }
+    }
+
+    /// Show everything held back by the reveal mode.
+    fn flush_reveal(&mut self) {
+        let pending = std::mem::take(&mut self.pending_reveal);
+        self.reveal(&pending);
+    }
+
+    /// Save the streaming answer after `autosave_ms`, unless a save is
+    /// already on its way.
+    fn schedule_autosave(&mut self) -> Option<Task<cosmic::Action<Message>>> {
+        if self.autosave_pending || self.config.autosave_ms == 0 {
+            return None;
+        }
+        self.autosave_pending = true;
+        let turn = self.turn;
+        let delay = Duration::from_millis(self.config.autosave_ms);
+        Some(Task::perform(tokio::time::sleep(delay), move |_| {
+            cosmic::Action::App(Message::AutosaveStream(turn))
+        }))
+    }
+
+    /// Unsent input worth keeping; whitespace alone isn't a draft.
+    fn draft(&self) -> &str {
+        if self.input_text.trim().is_empty() {
+            ""
+        } else {
+            &self.input_text
+        }
+    }
+
+    /// Save the unsent input with the conversation if it changed.
+    fn save_draft(&mut self) {
+        if self.draft() != self.conversation.draft {
+            self.save_history();
+        }
+    }
+
+    /// Load message images that don't have a thumbnail yet. Handles are
+    /// kept so the view doesn't decode them again on every frame.
+    fn cache_thumbnails(&mut self) {
+        for name in self.messages.iter().flat_map(|m| &m.images) {
+            if self.thumbnails.contains_key(name) {
+                continue;
+            }
+            if let Some(bytes) = history::image_path(name).and_then(|path| std::fs::read(path).ok())
+            {
+                self.thumbnails
+                    .insert(name.clone(), widget::image::Handle::from_bytes(bytes));
+            }
+        }
+    }
+
+    /// Keep a failed request's details, with the server and model it was
+    /// for, until the next request succeeds.
+    fn record_error(&mut self, action: &'static str, detail: &str) {
+        self.last_error = Some(LastError {
+            action,
+            url: self.config.ollama_url.clone(),
+            version: self.server.as_ref().map(|s| s.version.clone()),
+            model: self.active_model().to_string(),
+            detail: detail.to_string(),
+            time: history::unix_now().as_secs(),
+        });
+    }
+
+    /// Load the pending import, either appended to the current
+    /// conversation or as a new one carrying the file's title and model.
+    fn apply_import(&mut self, merge: bool) -> Task<cosmic::Action<Message>> {
+        // Switching conversations mid-stream would mix the two
+        if self.waiting {
+            return self.show_status(
+                "Wait for the current response before importing".to_string(),
+                true,
+            );
+        }
+        let Some(imported) = self.pending_import.take() else {
+            return Task::none();
+        };
+
+        let count = imported.messages.len();
+        if merge {
+            self.messages.extend(imported.messages);
+            self.token_usage += imported.tokens;
+        } else {
+            let mut conversation = Conversation::new();
+            conversation.title = imported.title;
+            conversation.model = imported.model;
+            conversation.persona = imported.persona;
+            self.open_conversation(conversation);
+            self.messages = imported.messages;
+            self.token_usage = imported.tokens;
+        }
+        self.cache_thumbnails();
+        self.save_history();
+        self.show_status(format!("Imported {} messages", count), false)
+    }
+
+    /// Show a conversation, loading its messages from disk.
+    fn open_conversation(&mut self, conversation: Conversation) {
+        self.save_draft();
+        // A conversation that never got a message (or a draft) isn't worth
+        // listing
+        let current = self.conversation.id;
+        if !self.messages.iter().any(|m| m.role == "user") && self.conversation.draft.is_empty() {
+            self.conversations.retain(|c| c.id != current);
+        }
+
+        let saved = history::load_history(conversation.id);
+        self.messages = if saved.messages.is_empty() {
+            vec![greeting(WELCOME_MESSAGE)]
+        } else {
+            saved.to_messages()
+        };
+        self.thumbnails.clear();
+        self.cache_thumbnails();
+        self.token_usage = saved.tokens;
+        self.chat_window = None;
+        self.chat_anchor = None;
+        self.attached_sources = None;
+        self.last_request = None;
+        self.missing_model = None;
+
+        if !self.conversations.iter().any(|c| c.id == conversation.id) {
+            self.conversations.insert(0, conversation.clone());
+        }
+        self.set_input(conversation.draft.clone());
+        self.prompt_history_index = None;
+        self.conversation = conversation;
+        self.refresh_conversation_titles();
+        // The model dropdown follows the conversation's model
+        self.refresh_model_options();
+    }
+
+    /// Copy the active conversation's metadata into the list, moving it to
+    /// the front as the most recently updated.
+    fn update_conversation_entry(&mut self) {
+        let id = self.conversation.id;
+        self.conversations.retain(|c| c.id != id);
+        self.conversations.insert(0, self.conversation.clone());
+        self.refresh_conversation_titles();
+    }
+
+    /// Rebuild the persona picker labels, with the default prompt first.
+    fn refresh_persona_options(&mut self) {
+        self.persona_options = std::iter::once("Default prompt".to_string())
+            .chain(self.config.personas.iter().map(|p| p.name.clone()))
+            .collect();
+    }
+
+    /// Rebuild the cached picker labels from the conversation list.
+    fn refresh_conversation_titles(&mut self) {
+        self.conversation_titles = self
+            .conversations
+            .iter()
+            .map(|c| c.display_title().to_string())
+            .collect();
+    }
+
+    /// Where a captured clipboard or selection is kept until it's sent.
+    fn attached_text(&mut self, source: ContextSource) -> Option<&mut Option<String>> {
+        match source {
+            ContextSource::Clipboard => Some(&mut self.attached_clipboard),
+            ContextSource::Selection => Some(&mut self.attached_selection),
+            ContextSource::SystemInfo | ContextSource::RecentErrors => None,
+        }
     }
 
-    fn poll_stream(&mut self) -> Task<cosmic::Action<Message>> {
-        if let Some(rx_arc) = &self.stream_rx {
-            let mut rx = rx_arc.lock().unwrap();
+    /// Deliver whatever the stream has sent since the last poll, in order.
+    fn poll_stream(&self) -> Task<cosmic::Action<Message>> {
+        let Some(mut rx) = self.stream_rx.as_ref().and_then(|rx| rx.try_lock().ok()) else {
+            return Task::none();
+        };
+        let mut messages = Vec::new();
+        loop {
             match rx.try_recv() {
-                Ok(event) => {
-                    drop(rx); // Release lock before returning
-                    let msg = match event {
-                        StreamEvent::Chunk(content) => Message::StreamChunk(content),
-                        StreamEvent::Done => Message::StreamDone,
-                        StreamEvent::Error(err) => Message::StreamError(err),
-                    };
-                    return Task::done(cosmic::Action::App(msg));
+                Ok(event) => messages.push(stream_message(Some(event))),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    messages.push(stream_message(None));
+                    break;
                 }
-                Err(mpsc::error::TryRecvError::Empty) => {
-                    drop(rx); // Release lock
-                    // No data yet, schedule another poll
-                    return Task::perform(
-                        async { tokio::time::sleep(tokio::time::Duration::from_millis(10)).await },
-                        |_| cosmic::Action::App(Message::PollStream),
-                    );
+            }
+        }
+        Task::stream(stream::iter(messages.into_iter().map(cosmic::Action::App)))
+    }
+
+    /// Reset streaming state and send any follow-ups queued meanwhile.
+    fn finish_stream(&mut self) -> Task<cosmic::Action<Message>> {
+        // Nothing may be left for the next answer or the typewriter tick
+        self.flush_reveal();
+        self.waiting = false;
+        self.stream_rx = None;
+        self.stream_task = None;
+        self.cancel = None;
+
+        if self.queued.is_empty() {
+            return Task::none();
+        }
+
+        // Batch everything typed while waiting into a single turn
+        let query = std::mem::take(&mut self.queued).join("\n\n");
+        self.send_query(query)
+    }
+
+    /// Record whether the server answered. The offline banner comes back
+    /// for the next outage once it has.
+    fn set_connection<T>(&mut self, result: &Result<T, String>) {
+        self.connection = match result {
+            Ok(_) => {
+                self.offline_dismissed = false;
+                ConnectionStatus::Connected
+            }
+            Err(err) => ConnectionStatus::Disconnected(err.clone()),
+        };
+    }
+
+    /// Append a user message and start streaming the response.
+    ///
+    /// A `/search <query>` message searches the web for the query whatever
+    /// the search mode.
+    fn send_query(&mut self, query: String) -> Task<cosmic::Action<Message>> {
+        let first_turn = !self.messages.iter().any(|m| m.role == "user");
+        let include_static = self.take_static_context(first_turn);
+
+        // The search override and attachment only apply to this message
+        let mut search_mode = self.next_search_mode();
+        self.search_mode = None;
+        let query = match web::search_command(&query) {
+            Some(search_query) => {
+                search_mode = SearchMode::Always;
+                search_query.to_string()
+            }
+            None => query,
+        };
+
+        let request = TurnRequest {
+            query: query.clone(),
+            model: self.active_model().to_string(),
+            include_static,
+            search_mode,
+            attachment: self.attachment.take(),
+            clipboard: self.attached_clipboard.take(),
+            selection: self.attached_selection.take(),
+            images: self
+                .image
+                .take()
+                .map(|image| image.data)
+                .into_iter()
+                .collect(),
+        };
+
+        // The history refers to the image file; the data only goes out
+        // with this turn
+        let mut tasks = Vec::new();
+        let mut images = Vec::new();
+        for data in &request.images {
+            match history::store_image(data) {
+                Ok(name) => images.push(name),
+                Err(err) => {
+                    tasks.push(self.show_status(format!("Could not save the image: {}", err), true))
                 }
-                Err(mpsc::error::TryRecvError::Disconnected) => {
-                    drop(rx); // Release lock
-                    // Channel closed unexpectedly
-                    self.waiting = false;
-                    self.stream_rx = None;
+            }
+        }
+        self.messages.push(HistoryMessage {
+            images,
+            ..HistoryMessage::new("user", query)
+        });
+        self.cache_thumbnails();
+        // Saved right away so the question survives a crash mid-answer
+        self.save_history();
+        // Sending always jumps back to the newest message
+        self.chat_scrolled_up = false;
+        tasks.push(self.start_turn(request));
+        tasks.push(self.follow_chat());
+        Task::batch(tasks)
+    }
+
+    /// Whether the next turn should attach static context. Consumes a
+    /// pending explicit refresh.
+    fn take_static_context(&mut self, first_turn: bool) -> bool {
+        let battery_saving = self.config.battery_saver && context::on_battery();
+        // An explicit refresh wins over battery saving
+        let include_static = self.refresh_static_context
+            || (!battery_saving && (!self.config.static_context_once || first_turn));
+        self.refresh_static_context = false;
+        include_static
+    }
+
+    /// Cancel the in-flight turn, keeping any partial response.
+    fn stop_generation(&mut self) -> Task<cosmic::Action<Message>> {
+        if !self.waiting {
+            return Task::none();
+        }
+        if let Some(cancel) = self.cancel.take() {
+            let _ = cancel.send(true);
+        }
+        // Aborting drops the response, which closes the connection
+        // and makes Ollama stop generating
+        if let Some(task) = self.stream_task.take() {
+            task.abort();
+        }
+        // Invalidate a StreamReady that is still on its way
+        self.turn += 1;
+
+        // Keep a partial response, but not an empty bubble
+        if self.stream_rx.is_some() {
+            self.flush_reveal();
+            if self
+                .messages
+                .last()
+                .is_some_and(|m| m.role == "assistant" && m.content.is_empty())
+            {
+                self.messages.pop();
+            }
+            self.save_history();
+        }
+        // Queued follow-ups wait for the next answer instead of going out
+        // now
+        let queued = std::mem::take(&mut self.queued);
+        let task = self.finish_stream();
+        self.queued = queued;
+        task
+    }
+
+    /// Drop the messages from `index` on, taking the tokens they used off
+    /// the conversation's count.
+    fn truncate_messages(&mut self, index: usize) {
+        for message in self.messages.get(index..).unwrap_or_default() {
+            self.token_usage -= message.usage;
+        }
+        self.messages.truncate(index);
+    }
+
+    /// Replace the answer to the last user message with a fresh one.
+    fn regenerate(&mut self) -> Task<cosmic::Action<Message>> {
+        let model = self.active_model().to_string();
+        self.regenerate_with(model)
+    }
+
+    /// Replace the last response with one from `model`, which may differ
+    /// from the conversation's.
+    fn regenerate_with(&mut self, model: String) -> Task<cosmic::Action<Message>> {
+        if self.waiting {
+            return Task::none();
+        }
+        let Some(last_user) = self.messages.iter().rposition(|m| m.role == "user") else {
+            return Task::none();
+        };
+
+        // Drops the answer along with any error bubble after it
+        self.truncate_messages(last_user + 1);
+        let query = self.messages[last_user].content.clone();
+
+        // Reuse the original request (search mode, attachment) when it was
+        // for this message
+        let request = match self.last_request.clone().filter(|r| r.query == query) {
+            Some(request) => TurnRequest { model, ..request },
+            None => {
+                let first_turn = !self.messages[..last_user].iter().any(|m| m.role == "user");
+                TurnRequest {
+                    query,
+                    model,
+                    include_static: self.take_static_context(first_turn),
+                    search_mode: self.config.search_mode,
+                    attachment: None,
+                    clipboard: None,
+                    selection: None,
+                    images: self.messages[last_user]
+                        .images
+                        .iter()
+                        .filter_map(|name| history::load_image(name))
+                        .collect(),
                 }
             }
+        };
+
+        self.chat_scrolled_up = false;
+        Task::batch([self.start_turn(request), self.follow_chat()])
+    }
+
+    /// Request an assistant response for the current conversation, streamed
+    /// unless streaming is turned off.
+    fn start_turn(&mut self, request: TurnRequest) -> Task<cosmic::Action<Message>> {
+        self.attached_sources = None;
+        self.missing_model = None;
+        self.typing_frame = 0;
+        self.turn_started = Some(Instant::now());
+
+        // Only send `think` to models (and servers) that understand it;
+        // others reject the field
+        let server_thinks = self.server.as_ref().is_none_or(|s| s.supports_thinking());
+        let think = self
+            .model_details
+            .get(&request.model)
+            .filter(|d| server_thinks && d.supports_thinking())
+            .map(|_| self.config.think);
+
+        self.waiting = true;
+        self.last_request = Some(request.clone());
+        self.turn += 1;
+        let turn = self.turn;
+        let (cancel_tx, cancel) = CancelToken::new();
+        self.cancel = Some(cancel_tx);
+
+        let mut config = self.config.clone();
+        config.model = request.model.clone();
+        config.system_prompt = self
+            .config
+            .persona_prompt(&self.conversation.persona)
+            .to_string();
+        // Stay within the model's context window when it's known
+        config.context_budget_chars = self.config.context_budget();
+        if let Some(context_length) = self
+            .model_details
+            .get(&request.model)
+            .and_then(|d| d.context_length)
+        {
+            config.context_budget_chars = config
+                .context_budget_chars
+                .min(context_length as usize * ollama::CHARS_PER_TOKEN);
         }
-        Task::none()
+        // ...and within the window it's asked to use
+        if let Some(num_ctx) = self.config.context_window() {
+            config.context_budget_chars = config
+                .context_budget_chars
+                .min(num_ctx as usize * ollama::CHARS_PER_TOKEN);
+        }
+        // The panel may show more than the model gets. Failed requests
+        // aren't part of the conversation, and earlier reasoning would only
+        // use up the context
+        let mut messages: Vec<ollama::Message> =
+            history::payload_window(&self.messages, self.config.history_limit())
+                .iter()
+                .filter(|m| !m.is_error())
+                .map(|m| {
+                    let content = if m.role == "assistant" {
+                        markdown::split_thinking(&m.content).answer
+                    } else {
+                        m.content.clone()
+                    };
+                    ollama::Message::new(m.role.clone(), content)
+                })
+                .collect();
+        // Images only go with the message they were attached to, so they
+        // aren't sent again (uncounted by the budget) on every turn
+        if !request.images.is_empty()
+            && let Some(message) = messages.iter_mut().rfind(|m| m.role == "user")
+        {
+            message.images = Some(request.images.clone());
+        }
+
+        // Raw completion always streams
+        if !self.config.streaming && !self.config.uses_generate() {
+            return Task::future(async move {
+                request_ollama_response(config, messages, request, think, cancel).await
+            })
+            .and_then(move |(response, sources, search_error)| {
+                Task::done(cosmic::Action::App(Message::ResponseComplete {
+                    turn,
+                    response,
+                    sources,
+                    search_error,
+                }))
+            });
+        }
+
+        Task::future(
+            async move { start_ollama_stream(config, messages, request, think, cancel).await },
+        )
+        .and_then(move |(rx, task, sources, search_error)| {
+            Task::done(cosmic::Action::App(Message::StreamReady(
+                turn,
+                Arc::new(Mutex::new(rx)),
+                task,
+                sources,
+                search_error,
+            )))
+        })
+    }
+
+    /// Rebuild the cached dropdown labels from the available models.
+    ///
+    /// If the configured model isn't installed it is appended as an extra
+    /// entry so the dropdown always shows what's active.
+    fn refresh_model_options(&mut self) {
+        self.model_options = self
+            .available_models
+            .iter()
+            .map(AvailableModel::label)
+            .collect();
+
+        let configured = self.active_model().to_string();
+        if !self.available_models.is_empty()
+            && !configured.is_empty()
+            && !self.available_models.iter().any(|m| m.name == configured)
+        {
+            self.model_options
+                .push(format!("{} (not installed)", configured));
+        }
+    }
+
+    /// An assistant message labelled with the model of the current turn.
+    fn answer(&self, content: String) -> HistoryMessage {
+        HistoryMessage {
+            model: self
+                .last_request
+                .as_ref()
+                .map(|request| request.model.clone())
+                .unwrap_or_default(),
+            ..HistoryMessage::new("assistant", content)
+        }
+    }
+
+    /// Append the latest user/assistant exchange to the transcript log.
+    fn log_last_turn(&self) {
+        let Some(path) = &self.config.transcript_log else {
+            return;
+        };
+
+        // The turn starts at the most recent user message
+        let Some(start) = self.messages.iter().rposition(|m| m.role == "user") else {
+            return;
+        };
+
+        let _ = history::append_transcript(path, self.active_model(), &self.messages[start..]);
+    }
+
+    /// Fetch /api/show details for a model unless they're already cached.
+    fn load_model_details(&self, name: String) -> Task<cosmic::Action<Message>> {
+        // /api/show is Ollama-only; other servers just get no `think`
+        if self.config.api_format != ApiFormat::Ollama || self.model_details.contains_key(&name) {
+            return Task::none();
+        }
+
+        let url = self.config.ollama_url.clone();
+        let api_key = self.config.api_key.clone();
+        Task::perform(
+            async move {
+                let result = OllamaClient::show_model(&url, &api_key, &name).await;
+                (name, result)
+            },
+            |(name, result)| cosmic::Action::App(Message::ModelDetailsLoaded(name, result)),
+        )
+    }
+
+    /// Route requests through the configured proxy, reporting a bad URL.
+    fn apply_proxy(&mut self) -> Task<cosmic::Action<Message>> {
+        match http::set_proxy(self.config.http_proxy.as_deref()) {
+            Ok(()) => Task::none(),
+            Err(err) => self.show_status(err, true),
+        }
+    }
+
+    /// Save the URL, model and timeout fields once typing pauses.
+    fn save_settings_later(&mut self) -> Task<cosmic::Action<Message>> {
+        self.settings_edit_id += 1;
+        let id = self.settings_edit_id;
+        Task::perform(tokio::time::sleep(SETTINGS_SAVE_DELAY), move |_| {
+            cosmic::Action::App(Message::SaveEditedSettings(id))
+        })
+    }
+
+    /// Persist the current config and report the outcome in the status line.
+    fn save_config(&mut self) -> Task<cosmic::Action<Message>> {
+        let result = match &self.config_ctx {
+            Some(ctx) => self.config.write_entry(ctx).map_err(|e| e.to_string()),
+            None => Err("config storage unavailable".to_string()),
+        };
+
+        match result {
+            Ok(()) => self.show_status("Settings saved".to_string(), false),
+            Err(err) => self.show_status(format!("Failed to save settings: {}", err), true),
+        }
+    }
+
+    /// Replace limits set to 0 with their defaults, store the fix and say
+    /// which settings were reset.
+    fn reset_zero_limits(&mut self) -> Task<cosmic::Action<Message>> {
+        let reset = self.config.reset_zero_limits();
+        if reset.is_empty() {
+            return Task::none();
+        }
+        if let Some(ctx) = &self.config_ctx {
+            let _ = self.config.write_entry(ctx);
+        }
+        self.show_status(
+            format!("{} can't be 0, reset to the default", reset.join(", ")),
+            true,
+        )
+    }
+
+    /// Show a status message that clears itself after a few seconds.
+    fn show_status(&mut self, text: String, is_error: bool) -> Task<cosmic::Action<Message>> {
+        self.status_id += 1;
+        self.status = Some(Status { text, is_error });
+
+        let id = self.status_id;
+        Task::perform(
+            async { tokio::time::sleep(tokio::time::Duration::from_secs(3)).await },
+            move |_| cosmic::Action::App(Message::ClearStatus(id)),
+        )
     }
 
     fn handle_toggle_popup(&mut self) -> Task<cosmic::Action<Message>> {
@@ -464,6 +4809,7 @@ impl AppModel {
 
         let new_id = Id::unique();
         self.popup.replace(new_id);
+        self.unread = false;
 
         let mut popup_settings = self.core.applet.get_popup_settings(
             self.core.main_window_id().unwrap(),
@@ -473,15 +4819,19 @@ impl AppModel {
             None,
         );
 
+        // Let larger text have more room before it has to wrap or scroll
+        let scale = self.config.text_scale().max(1.0);
+        let (width, height) = self.config.popup_size();
         popup_settings.positioner.size_limits = Limits::NONE
-            .max_width(400.0)
-            .min_width(350.0)
-            .min_height(400.0)
-            .max_height(600.0);
+            .max_width((width * scale).min(config::MAX_POPUP_WIDTH))
+            .min_width(width.min(350.0))
+            .min_height(height.min(400.0))
+            .max_height((height * scale).min(config::MAX_POPUP_HEIGHT));
 
-        // Load models when popup opens
+        // Load models and check the server when popup opens
         let popup_task = get_popup(popup_settings);
         let load_task = Task::done(cosmic::Action::App(Message::LoadModels));
-        Task::batch([popup_task, load_task])
+        let check_task = Task::done(cosmic::Action::App(Message::CheckConnection));
+        Task::batch([popup_task, load_task, check_task])
     }
 }
@@ -2,25 +2,465 @@
 
 //! Application configuration stored via cosmic-config.
 
+use crate::ollama::{ApiFormat, DEFAULT_SYSTEM_PROMPT};
+use crate::web::{SearchBackend, SearchMode};
 use cosmic::cosmic_config::{self, CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434/api/chat";
 pub const DEFAULT_MODEL: &str = "llama3.2:3b";
+pub const DEFAULT_WEB_RELATED_LIMIT: usize = 5;
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+pub const DEFAULT_STREAM_POLL_MS: u64 = 10;
+/// Ollama's own keep-alive; left out of requests.
+pub const DEFAULT_KEEP_ALIVE: &str = "5m";
+pub const DEFAULT_THINKING_PLACEHOLDER: &str = "Thinking...";
+/// Ollama's own sampling defaults; matching values are left out of requests.
+pub const DEFAULT_TEMPERATURE: f32 = 0.8;
+pub const DEFAULT_TOP_P: f32 = 0.9;
+pub const DEFAULT_MAX_HISTORY: usize = 100;
+pub const DEFAULT_AUTOSAVE_MS: u64 = 1000;
+pub const DEFAULT_MAX_CLIPBOARD_BYTES: usize = 2000;
+pub const DEFAULT_MAX_ERROR_BYTES: usize = 1500;
+pub const DEFAULT_ERROR_PRIORITY: &str = "err";
+pub const DEFAULT_ERROR_LINES: usize = 5;
+/// Priority names journalctl accepts for `-p`.
+const JOURNAL_PRIORITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+pub const DEFAULT_CONTEXT_BUDGET_CHARS: usize = 16_000;
+pub const DEFAULT_SEARCH_CACHE_SECS: u64 = 600;
+/// Range of the context window size, in tokens.
+pub const MIN_NUM_CTX: u32 = 512;
+pub const MAX_NUM_CTX: u32 = 131_072;
+/// Range of the chat text scale.
+pub const MIN_FONT_SCALE: f32 = 0.75;
+pub const MAX_FONT_SCALE: f32 = 2.0;
+/// Popup size bounds, in logical pixels.
+pub const DEFAULT_POPUP_WIDTH: f32 = 400.0;
+pub const DEFAULT_POPUP_HEIGHT: f32 = 600.0;
+pub const MIN_POPUP_SIZE: f32 = 300.0;
+pub const MAX_POPUP_WIDTH: f32 = 1600.0;
+pub const MAX_POPUP_HEIGHT: f32 = 1400.0;
 
-#[derive(Debug, Clone, CosmicConfigEntry, Eq, PartialEq)]
+/// How a streamed answer appears in its bubble.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RevealMode {
+    /// Each chunk as soon as it arrives.
+    #[default]
+    Stream,
+    /// The whole answer once it's complete.
+    Instant,
+    /// A character at a time at a steady pace, whatever the chunk size.
+    Typewriter,
+}
+
+impl RevealMode {
+    pub const ALL: [Self; 3] = [Self::Stream, Self::Instant, Self::Typewriter];
+
+    /// Name shown in the settings dropdown.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Stream => "As it arrives",
+            Self::Instant => "When complete",
+            Self::Typewriter => "Typewriter",
+        }
+    }
+}
+
+/// A named system prompt a conversation can use instead of the default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    pub prompt: String,
+}
+
+impl Persona {
+    fn new(name: &str, prompt: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            prompt: prompt.to_string(),
+        }
+    }
+}
+
+/// Personas offered out of the box.
+fn builtin_personas() -> Vec<Persona> {
+    vec![
+        Persona::new(
+            "Coding helper",
+            "You are a senior software engineer. Answer with working, idiomatic code \
+             and explain only what isn't obvious from it. Point out bugs and edge cases.",
+        ),
+        Persona::new(
+            "Writing editor",
+            "You are a careful editor. Improve the clarity, grammar and flow of the text \
+             you're given while keeping its meaning and voice. Briefly list what you changed.",
+        ),
+        Persona::new(
+            "Shell expert",
+            "You are a Linux shell expert on a COSMIC desktop. Answer with commands that \
+             can be pasted into a terminal, say what each one does, and warn before \
+             anything destructive.",
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, CosmicConfigEntry, PartialEq)]
 #[version = 1]
 pub struct Config {
-    /// Ollama API endpoint URL.
+    /// Ollama API endpoint URL. For OpenAI-compatible servers this is the
+    /// `/v1/chat/completions` endpoint.
     pub ollama_url: String,
+    /// Wire format spoken by the server at `ollama_url`.
+    pub api_format: ApiFormat,
+    /// Bearer token for servers behind an authenticating proxy. Empty
+    /// sends no `Authorization` header.
+    pub api_key: String,
+    /// Proxy for all outbound requests (Ollama and web search), e.g.
+    /// "http://proxy:3128". `None` uses the `HTTP_PROXY`, `HTTPS_PROXY`
+    /// and `NO_PROXY` environment variables.
+    pub http_proxy: Option<String>,
     /// Model to use for chat completions.
     pub model: String,
+    /// Enable reasoning output on models that support thinking.
+    pub think: bool,
+    /// Enter sends the message and Shift+Enter starts a new line. Off,
+    /// Enter starts a new line and Ctrl+Enter sends.
+    pub enter_sends: bool,
+    /// Queue messages submitted while a response is streaming.
+    pub queue_submissions: bool,
+    /// Only attach static context (system info, recent errors) on the first
+    /// turn of a conversation or after an explicit refresh.
+    pub static_context_once: bool,
+    /// Render the chat as a plain role-prefixed transcript for screen readers.
+    pub plain_text_mode: bool,
+    /// Draw your messages in the accent style; off, both sides use the
+    /// neutral card style.
+    pub user_bubble_accent: bool,
+    /// Put your messages on the right and answers on the left, like a
+    /// messaging app, instead of full width.
+    pub align_bubbles: bool,
+    /// Wrap long lines in code blocks; off, wide code scrolls sideways so
+    /// commands stay on one line.
+    pub wrap_code: bool,
+    /// How answers appear while they stream.
+    pub reveal_mode: RevealMode,
+    /// Size of the chat and input text relative to the default.
+    pub font_scale: f32,
+    /// Largest popup width.
+    pub popup_width: f32,
+    /// Largest popup height.
+    pub popup_height: f32,
+    /// Append-only JSONL archive of every completed turn (never trimmed).
+    pub transcript_log: Option<PathBuf>,
+    /// Service used for web searches.
+    pub search_backend: SearchBackend,
+    /// When to search: on keyword matches, always or never. Never means no
+    /// traffic beyond the model server unless a message asks for a search.
+    pub search_mode: SearchMode,
+    /// Seconds a web search result is reused for the same query; 0 always
+    /// searches again.
+    pub search_cache_secs: u64,
+    /// Maximum number of related topics included from a web search.
+    pub web_related_limit: usize,
+    /// Model to retry with once when the configured one isn't installed.
+    pub fallback_model: Option<String>,
+    /// Seconds between model list refreshes while the popup is open; 0
+    /// only loads the list when the popup opens.
+    pub model_refresh_secs: u64,
+    /// Keep every message on disk and in the panel; only the payload sent
+    /// to the model is limited to the most recent messages.
+    pub full_scrollback: bool,
+    /// Milliseconds between saves of an answer while it streams, so a
+    /// crash keeps what arrived; 0 only saves once it's complete.
+    pub autosave_ms: u64,
+    /// Placeholder shown while waiting for the first response token.
+    pub thinking_placeholder: String,
+    /// Drop the `<think>` reasoning of reasoning models from answers once
+    /// they finish, instead of keeping it in a collapsed section.
+    pub strip_reasoning: bool,
+    /// Ask for short answers by appending a brevity instruction to the
+    /// system prompt.
+    pub concise_mode: bool,
+    /// Token cap (`num_predict`) applied while concise mode is on.
+    pub concise_max_tokens: Option<u32>,
+    /// Skip the heavier context captures (system info, journal errors)
+    /// while on battery. An attached clipboard or selection is still sent.
+    pub battery_saver: bool,
+    /// Sampling temperature; higher is more creative.
+    pub temperature: f32,
+    /// Nucleus sampling cutoff.
+    pub top_p: f32,
+    /// Context window size in tokens (`num_ctx`); `None` uses the model's
+    /// default. Larger windows use more VRAM.
+    pub num_ctx: Option<u32>,
+    /// Generation stops when the model produces any of these strings.
+    pub stop_sequences: Vec<String>,
+    /// Instructions sent as the system message. Empty sends none (context
+    /// such as the clipboard is still attached).
+    pub system_prompt: String,
+    /// Alternative system prompts a conversation can pick from.
+    pub personas: Vec<Persona>,
+    /// Seconds to wait for Ollama to send anything before giving up.
+    pub timeout_secs: u64,
+    /// Times to retry a refused or dropped connection before reporting it.
+    pub max_retries: u32,
+    /// How long Ollama keeps the model loaded after a request: a duration
+    /// such as "5m", "0" to unload at once or "-1" to keep it loaded.
+    pub keep_alive: String,
+    /// Stream responses as they're generated. Off asks for the whole
+    /// response at once, for proxies that buffer or break streaming.
+    pub streaming: bool,
+    /// Check for streamed chunks every `stream_poll_ms` instead of
+    /// receiving each one as it arrives; a fallback for setups where the
+    /// stream subscription misbehaves.
+    pub stream_polling: bool,
+    /// Milliseconds between checks with `stream_polling`. Higher values
+    /// use less CPU at the cost of chunk latency.
+    pub stream_poll_ms: u64,
+    /// Send each message to Ollama's generate API as a bare prompt, without
+    /// history, system prompt or context, for code-completion models.
+    pub raw_completion: bool,
+    /// Offer a button for attaching the clipboard contents as context.
+    pub include_clipboard: bool,
+    /// Offer a button for attaching the primary selection as context.
+    pub include_selection: bool,
+    /// Attach a short system summary (OS, kernel, memory).
+    pub include_system_info: bool,
+    /// Attach recent journal errors. Off by default since the journal can
+    /// contain sensitive details.
+    pub include_recent_errors: bool,
+    /// Messages kept on disk (unless `full_scrollback` is set) and sent to
+    /// the model.
+    pub max_history: usize,
+    /// Clipboard or selection contents at least this large are not attached.
+    pub max_clipboard_bytes: usize,
+    /// Journal output at least this large is not attached.
+    pub max_error_bytes: usize,
+    /// Mask things that look like passwords, keys and tokens in attached
+    /// text and journal errors before they're sent.
+    pub redact_secrets: bool,
+    /// Lowest journal priority attached as a recent error, e.g. "err" or
+    /// "warning".
+    pub error_priority: String,
+    /// Journal lines attached as recent errors.
+    pub error_lines: usize,
+    /// Read the user's journal (`--user`) instead of the system one.
+    pub error_user_journal: bool,
+    /// Only attach errors logged since this time, in any form journalctl
+    /// understands ("1h ago", "today"); empty attaches the latest ones
+    /// however old.
+    pub error_since: String,
+    /// Characters of system prompt and history sent per request; the
+    /// oldest exchanges are dropped beyond this. Lowered further to fit the
+    /// model's context window when it's known.
+    pub context_budget_chars: usize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             ollama_url: DEFAULT_OLLAMA_URL.to_string(),
+            api_format: ApiFormat::default(),
+            api_key: String::new(),
+            http_proxy: None,
             model: DEFAULT_MODEL.to_string(),
+            think: false,
+            enter_sends: true,
+            queue_submissions: true,
+            static_context_once: false,
+            plain_text_mode: false,
+            user_bubble_accent: true,
+            align_bubbles: false,
+            wrap_code: false,
+            reveal_mode: RevealMode::default(),
+            font_scale: 1.0,
+            popup_width: DEFAULT_POPUP_WIDTH,
+            popup_height: DEFAULT_POPUP_HEIGHT,
+            transcript_log: None,
+            search_backend: SearchBackend::default(),
+            search_mode: SearchMode::default(),
+            search_cache_secs: DEFAULT_SEARCH_CACHE_SECS,
+            web_related_limit: DEFAULT_WEB_RELATED_LIMIT,
+            fallback_model: None,
+            model_refresh_secs: 0,
+            full_scrollback: false,
+            autosave_ms: DEFAULT_AUTOSAVE_MS,
+            thinking_placeholder: DEFAULT_THINKING_PLACEHOLDER.to_string(),
+            strip_reasoning: false,
+            concise_mode: false,
+            concise_max_tokens: None,
+            battery_saver: false,
+            temperature: DEFAULT_TEMPERATURE,
+            top_p: DEFAULT_TOP_P,
+            num_ctx: None,
+            stop_sequences: Vec::new(),
+            system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+            personas: builtin_personas(),
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            keep_alive: DEFAULT_KEEP_ALIVE.to_string(),
+            streaming: true,
+            stream_polling: false,
+            stream_poll_ms: DEFAULT_STREAM_POLL_MS,
+            raw_completion: false,
+            include_clipboard: true,
+            include_selection: true,
+            include_system_info: true,
+            include_recent_errors: false,
+            max_history: DEFAULT_MAX_HISTORY,
+            max_clipboard_bytes: DEFAULT_MAX_CLIPBOARD_BYTES,
+            max_error_bytes: DEFAULT_MAX_ERROR_BYTES,
+            redact_secrets: true,
+            error_priority: DEFAULT_ERROR_PRIORITY.to_string(),
+            error_lines: DEFAULT_ERROR_LINES,
+            error_user_journal: false,
+            error_since: String::new(),
+            context_budget_chars: DEFAULT_CONTEXT_BUDGET_CHARS,
+        }
+    }
+}
+
+impl Config {
+    /// `max_history`, with 0 (which would send nothing) read as the default.
+    pub fn history_limit(&self) -> usize {
+        nonzero_or_default(self.max_history, DEFAULT_MAX_HISTORY)
+    }
+
+    /// `max_clipboard_bytes`, with 0 read as the default.
+    pub fn clipboard_limit(&self) -> usize {
+        nonzero_or_default(self.max_clipboard_bytes, DEFAULT_MAX_CLIPBOARD_BYTES)
+    }
+
+    /// `max_error_bytes`, with 0 read as the default.
+    pub fn error_limit(&self) -> usize {
+        nonzero_or_default(self.max_error_bytes, DEFAULT_MAX_ERROR_BYTES)
+    }
+
+    /// Put the default back into limits set to 0, which would send or
+    /// attach nothing. Returns the names of the settings that were reset.
+    pub fn reset_zero_limits(&mut self) -> Vec<&'static str> {
+        let limits = [
+            ("max_history", &mut self.max_history, DEFAULT_MAX_HISTORY),
+            (
+                "max_clipboard_bytes",
+                &mut self.max_clipboard_bytes,
+                DEFAULT_MAX_CLIPBOARD_BYTES,
+            ),
+            (
+                "max_error_bytes",
+                &mut self.max_error_bytes,
+                DEFAULT_MAX_ERROR_BYTES,
+            ),
+            ("error_lines", &mut self.error_lines, DEFAULT_ERROR_LINES),
+            (
+                "context_budget_chars",
+                &mut self.context_budget_chars,
+                DEFAULT_CONTEXT_BUDGET_CHARS,
+            ),
+        ];
+        let mut reset = Vec::new();
+        for (name, value, default) in limits {
+            if *value == 0 {
+                *value = default;
+                reset.push(name);
+            }
         }
+        reset
+    }
+
+    /// The prompt of the named persona, or `system_prompt` when there's no
+    /// persona by that name (including the empty one).
+    pub fn persona_prompt(&self, persona: &str) -> &str {
+        self.personas
+            .iter()
+            .find(|p| p.name == persona)
+            .map_or(&self.system_prompt, |p| &p.prompt)
+    }
+
+    /// `error_priority` if journalctl knows it (by name or as 0-7),
+    /// otherwise the default.
+    pub fn journal_priority(&self) -> &str {
+        let priority = self.error_priority.trim();
+        let known = JOURNAL_PRIORITIES.contains(&priority)
+            || priority.parse::<u8>().is_ok_and(|level| level <= 7);
+        if known {
+            priority
+        } else {
+            DEFAULT_ERROR_PRIORITY
+        }
+    }
+
+    /// `error_lines`, with 0 read as the default.
+    pub fn journal_lines(&self) -> usize {
+        nonzero_or_default(self.error_lines, DEFAULT_ERROR_LINES)
+    }
+
+    /// `context_budget_chars`, with 0 read as the default.
+    pub fn context_budget(&self) -> usize {
+        nonzero_or_default(self.context_budget_chars, DEFAULT_CONTEXT_BUDGET_CHARS)
+    }
+
+    /// Whether messages go to the generate API, which only the native
+    /// Ollama API has.
+    pub fn uses_generate(&self) -> bool {
+        self.raw_completion && self.api_format == ApiFormat::Ollama
+    }
+
+    /// `num_ctx` kept within the supported range.
+    pub fn context_window(&self) -> Option<u32> {
+        self.num_ctx.map(|n| n.clamp(MIN_NUM_CTX, MAX_NUM_CTX))
+    }
+
+    /// `popup_width` and `popup_height` kept within the supported range.
+    pub fn popup_size(&self) -> (f32, f32) {
+        let clamp = |value: f32, default: f32, max: f32| {
+            if value.is_finite() {
+                value.clamp(MIN_POPUP_SIZE, max)
+            } else {
+                default
+            }
+        };
+        (
+            clamp(self.popup_width, DEFAULT_POPUP_WIDTH, MAX_POPUP_WIDTH),
+            clamp(self.popup_height, DEFAULT_POPUP_HEIGHT, MAX_POPUP_HEIGHT),
+        )
+    }
+
+    /// `font_scale` kept within the supported range, in case the config
+    /// file was edited by hand.
+    pub fn text_scale(&self) -> f32 {
+        if self.font_scale.is_finite() {
+            self.font_scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE)
+        } else {
+            1.0
+        }
+    }
+}
+
+fn nonzero_or_default(value: usize, default: usize) -> usize {
+    if value == 0 { default } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_zero_limits() {
+        let mut config = Config {
+            max_history: 0,
+            error_lines: 0,
+            max_error_bytes: 1,
+            ..Default::default()
+        };
+        assert_eq!(config.reset_zero_limits(), ["max_history", "error_lines"]);
+        assert_eq!(config.max_history, DEFAULT_MAX_HISTORY);
+        assert_eq!(config.error_lines, DEFAULT_ERROR_LINES);
+        assert_eq!(config.max_error_bytes, 1);
+        assert!(config.reset_zero_limits().is_empty());
     }
 }
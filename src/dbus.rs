@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: GPL-3.0
+
+//! D-Bus interface for asking the applet a question from outside.
+//!
+//! The running applet owns `BUS_NAME` and forwards `Ask` calls to the UI,
+//! which opens the popup with the question in the input. Running
+//! `cosmic-applet-ollama --ask "..."` makes the call, so a global shortcut
+//! or another tool can hand over e.g. an error message.
+
+use futures_util::{Stream, stream};
+use tokio::sync::mpsc;
+use zbus::{Connection, connection, fdo, interface};
+
+/// Well-known name (and interface name) the applet registers.
+pub const BUS_NAME: &str = "com.github.paulwade.CosmicAppletOllama";
+const OBJECT_PATH: &str = "/com/github/paulwade/CosmicAppletOllama";
+
+/// A question sent from outside the applet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AskRequest {
+    /// Text put in the message input.
+    pub query: String,
+    /// Send it right away instead of leaving it to be edited.
+    pub submit: bool,
+}
+
+struct Service {
+    tx: mpsc::Sender<AskRequest>,
+}
+
+#[interface(name = "com.github.paulwade.CosmicAppletOllama")]
+impl Service {
+    /// Open the popup with `query` in the input, sending it if `submit`.
+    async fn ask(&self, query: String, submit: bool) -> fdo::Result<()> {
+        self.tx
+            .send(AskRequest { query, submit })
+            .await
+            .map_err(|_| fdo::Error::Failed("The applet is shutting down".to_string()))
+    }
+}
+
+/// Serve the interface, yielding each request as it comes in.
+///
+/// Ends without yielding anything if there's no session bus or the name
+/// is already taken by another instance.
+pub fn serve() -> impl Stream<Item = AskRequest> {
+    stream::unfold(None, |state: Option<Serving>| async move {
+        let (connection, mut rx) = match state {
+            Some(state) => state,
+            None => register().await?,
+        };
+        // The connection has to stay alive for the name to stay registered
+        let request = rx.recv().await?;
+        Some((request, Some((connection, rx))))
+    })
+}
+
+/// The bus connection holding the name, and where its calls arrive.
+type Serving = (Connection, mpsc::Receiver<AskRequest>);
+
+async fn register() -> Option<Serving> {
+    let (tx, rx) = mpsc::channel(8);
+    let connection = connection::Builder::session()
+        .ok()?
+        .name(BUS_NAME)
+        .ok()?
+        .serve_at(OBJECT_PATH, Service { tx })
+        .ok()?
+        .build()
+        .await
+        .ok()?;
+    Some((connection, rx))
+}
+
+/// Hand a question to the running applet.
+pub async fn ask(query: &str, submit: bool) -> Result<(), String> {
+    let connection = Connection::session()
+        .await
+        .map_err(|e| format!("Could not connect to the session bus: {}", e))?;
+    connection
+        .call_method(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            Some(BUS_NAME),
+            "Ask",
+            &(query, submit),
+        )
+        .await
+        .map_err(|e| format!("The applet isn't running or didn't answer: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,479 @@
+// SPDX-License-Identifier: GPL-3.0
+
+//! Lightweight syntax highlighting for code blocks.
+//!
+//! A single-pass tokenizer that knows the comments, strings, numbers and
+//! keywords of the languages models write most often. It doesn't parse
+//! anything; it only has to look right in a chat bubble.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+/// Number of finished code blocks whose highlighting is kept.
+const HIGHLIGHT_CACHE_SIZE: usize = 64;
+
+/// Highlighting of recently shown code blocks, shared by all conversations.
+static HIGHLIGHT_CACHE: LazyLock<Mutex<HighlightCache>> = LazyLock::new(Default::default);
+
+/// What a run of code is, for picking its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// A run of code sharing one kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+}
+
+/// How a language spells the things that get highlighted.
+struct Language {
+    keywords: &'static [&'static str],
+    /// Prefixes that comment out the rest of the line.
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// SQL keywords are written in either case.
+    ignore_case: bool,
+}
+
+const BASH: Language = Language {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "local", "export", "sudo", "echo", "exit",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    ignore_case: false,
+};
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    // Not `'`, which is mostly lifetimes
+    quotes: &['"'],
+    ignore_case: false,
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+        "try", "while", "with", "yield",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    ignore_case: false,
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "of",
+        "return",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "while",
+        "yield",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    ignore_case: false,
+};
+
+const C: Language = Language {
+    keywords: &[
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "float",
+        "for",
+        "if",
+        "include",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "nullptr",
+        "private",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "true",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "void",
+        "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    ignore_case: false,
+};
+
+const GO: Language = Language {
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "for",
+        "func",
+        "go",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '`'],
+    ignore_case: false,
+};
+
+const SQL: Language = Language {
+    keywords: &[
+        "select", "from", "where", "and", "or", "not", "insert", "into", "values", "update", "set",
+        "delete", "create", "table", "drop", "alter", "join", "left", "right", "inner", "on",
+        "group", "by", "order", "limit", "as", "null", "is", "primary", "key",
+    ],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\''],
+    ignore_case: true,
+};
+
+/// JSON, TOML, YAML and INI: only values stand out.
+const DATA: Language = Language {
+    keywords: &["true", "false", "null"],
+    line_comments: &["#", ";"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    ignore_case: false,
+};
+
+/// The language for a code fence's tag, if it's one we know.
+fn language(lang: &str) -> Option<&'static Language> {
+    Some(match lang.trim().to_ascii_lowercase().as_str() {
+        "bash" | "sh" | "shell" | "zsh" | "console" => &BASH,
+        "rust" | "rs" => &RUST,
+        "python" | "py" => &PYTHON,
+        "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => &JAVASCRIPT,
+        "c" | "h" | "cpp" | "c++" | "hpp" | "java" => &C,
+        "go" | "golang" => &GO,
+        "sql" => &SQL,
+        "json" | "toml" | "yaml" | "yml" | "ini" => &DATA,
+        _ => return None,
+    })
+}
+
+/// Split code into highlighted runs, or `None` for an unknown language.
+pub fn highlight(code: &str, lang: &str) -> Option<Vec<Token>> {
+    let language = language(lang)?;
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut push = |kind: TokenKind, text: &str| match tokens.last_mut() {
+        Some(last) if last.kind == kind => last.text.push_str(text),
+        _ => tokens.push(Token {
+            kind,
+            text: text.to_string(),
+        }),
+    };
+
+    let mut rest = code;
+    // Whether the previous character ends a word, so `#` in `$#` or a
+    // digit in `x1` isn't mistaken for a comment or number
+    let mut word_start = true;
+
+    while let Some(c) = rest.chars().next() {
+        if word_start
+            && let Some(prefix) = language
+                .line_comments
+                .iter()
+                .find(|p| rest.starts_with(**p))
+        {
+            let end = rest.find('\n').unwrap_or(rest.len()).max(prefix.len());
+            push(TokenKind::Comment, &rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if let Some((open, close)) = language.block_comment
+            && let Some(inner) = rest.strip_prefix(open)
+        {
+            let end = inner
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+            push(TokenKind::Comment, &rest[..end]);
+            rest = &rest[end..];
+            word_start = true;
+            continue;
+        }
+
+        if language.quotes.contains(&c) {
+            let end = string_end(rest, c);
+            push(TokenKind::String, &rest[..end]);
+            rest = &rest[end..];
+            word_start = true;
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' {
+            let number = word_start && c.is_ascii_digit();
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || (number && c == '.')))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            let kind = if number {
+                TokenKind::Number
+            } else if is_keyword(language, word) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Plain
+            };
+            push(kind, word);
+            rest = &rest[end..];
+            word_start = false;
+            continue;
+        }
+
+        push(TokenKind::Plain, &rest[..c.len_utf8()]);
+        rest = &rest[c.len_utf8()..];
+        // `$`, `-` and `.` glue onto what follows in shell and paths
+        word_start = !matches!(c, '$' | '-' | '.' | '/');
+    }
+
+    Some(tokens)
+}
+
+/// Byte length of the string literal at the start of `text`, up to the
+/// closing quote (or the end, if it's unclosed).
+fn string_end(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+struct CacheEntry {
+    lang: String,
+    code: String,
+    tokens: Option<Arc<[Token]>>,
+}
+
+/// Recently highlighted blocks, least recently used first.
+#[derive(Default)]
+struct HighlightCache {
+    entries: VecDeque<CacheEntry>,
+}
+
+impl HighlightCache {
+    /// The tokens for a block, highlighting it only if it isn't cached.
+    fn get_or_highlight(&mut self, code: &str, lang: &str) -> Option<Arc<[Token]>> {
+        let cached = self
+            .entries
+            .iter()
+            .position(|entry| entry.lang == lang && entry.code == code)
+            .and_then(|index| self.entries.remove(index));
+        let entry = cached.unwrap_or_else(|| CacheEntry {
+            lang: lang.to_string(),
+            code: code.to_string(),
+            tokens: highlight(code, lang).map(Arc::from),
+        });
+        let tokens = entry.tokens.clone();
+        if self.entries.len() >= HIGHLIGHT_CACHE_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        tokens
+    }
+}
+
+/// Like [`highlight`], but remembers the result so a finished block isn't
+/// tokenized again every time the view is drawn.
+pub fn highlight_cached(code: &str, lang: &str) -> Option<Arc<[Token]>> {
+    HIGHLIGHT_CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_highlight(code, lang)
+}
+
+fn is_keyword(language: &Language, word: &str) -> bool {
+    if language.ignore_case {
+        let word = word.to_ascii_lowercase();
+        language.keywords.contains(&word.as_str())
+    } else {
+        language.keywords.contains(&word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(kind: TokenKind, text: &str) -> Token {
+        Token {
+            kind,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_highlight_bash() {
+        let tokens = highlight("if [ $# -gt 1 ]; then echo \"hi\" # greet\nfi", "bash").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Keyword, "if"),
+                token(TokenKind::Plain, " [ $# -gt "),
+                token(TokenKind::Number, "1"),
+                token(TokenKind::Plain, " ]; "),
+                token(TokenKind::Keyword, "then"),
+                token(TokenKind::Plain, " "),
+                token(TokenKind::Keyword, "echo"),
+                token(TokenKind::Plain, " "),
+                token(TokenKind::String, "\"hi\""),
+                token(TokenKind::Plain, " "),
+                token(TokenKind::Comment, "# greet"),
+                token(TokenKind::Plain, "\n"),
+                token(TokenKind::Keyword, "fi"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlight_rust() {
+        let tokens = highlight("let x1 = \"a\\\"b\"; /* done */", "rs").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Keyword, "let"),
+                token(TokenKind::Plain, " x1 = "),
+                token(TokenKind::String, "\"a\\\"b\""),
+                token(TokenKind::Plain, "; "),
+                token(TokenKind::Comment, "/* done */"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlight_unknown_language() {
+        assert_eq!(highlight("whatever", ""), None);
+        assert_eq!(highlight("whatever", "brainfuck"), None);
+        // SQL keywords in any case
+        assert_eq!(
+            highlight("SELECT", "sql").unwrap(),
+            vec![token(TokenKind::Keyword, "SELECT")]
+        );
+    }
+
+    #[test]
+    fn test_highlight_cache() {
+        let mut cache = HighlightCache::default();
+        let first = cache.get_or_highlight("let x = 1;", "rust").unwrap();
+        let again = cache.get_or_highlight("let x = 1;", "rust").unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(cache.entries.len(), 1);
+
+        // Same code in another language is a separate block
+        assert!(cache.get_or_highlight("let x = 1;", "").is_none());
+        assert_eq!(cache.entries.len(), 2);
+
+        for i in 0..HIGHLIGHT_CACHE_SIZE {
+            cache.get_or_highlight(&i.to_string(), "rust");
+        }
+        assert_eq!(cache.entries.len(), HIGHLIGHT_CACHE_SIZE);
+        let evicted = cache.get_or_highlight("let x = 1;", "rust").unwrap();
+        assert!(!Arc::ptr_eq(&first, &evicted));
+    }
+}
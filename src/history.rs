@@ -4,76 +4,275 @@
 //!
 //! Saves and loads chat history to/from the XDG data directory.
 
+use crate::ollama::{GenerationStats, TokenUsage};
+use base64::prelude::{BASE64_STANDARD, Engine};
 use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
 use std::fs;
-use std::io::{self, BufReader, BufWriter};
-use std::path::PathBuf;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Maximum number of messages to keep in history.
-pub const MAX_HISTORY_SIZE: usize = 100;
-
-/// A single chat message in the history.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single chat message, as shown in the panel and saved to history.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct HistoryMessage {
     /// Role: "user" or "assistant"
     pub role: String,
     /// Message content
     pub content: String,
+    /// Unix timestamp (seconds) when the message was sent or finished;
+    /// 0 for messages saved before timestamps were recorded.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// Images sent along with a user message, by their name in the image
+    /// directory (see `store_image`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Model that wrote an assistant message; empty for user messages and
+    /// answers saved before it was recorded.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub model: String,
+    /// Tokens the request for an assistant message used; zero for user
+    /// messages and answers saved before it was recorded.
+    #[serde(default, skip_serializing_if = "TokenUsage::is_zero")]
+    pub usage: TokenUsage,
+    /// Generation speed of an assistant message, when the server reported
+    /// it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<GenerationStats>,
+}
+
+/// Start of the assistant bubble a failed request leaves behind.
+const ERROR_PREFIX: &str = "Error: ";
+
+impl HistoryMessage {
+    /// A message sent or received now.
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+            timestamp: unix_now().as_secs(),
+            images: Vec::new(),
+            model: String::new(),
+            usage: TokenUsage::default(),
+            stats: None,
+        }
+    }
+
+    /// An assistant bubble reporting a failed request.
+    pub fn error(detail: impl std::fmt::Display) -> Self {
+        Self::new("assistant", format!("{}{}", ERROR_PREFIX, detail))
+    }
+
+    /// Whether this is a failed request's bubble rather than an answer.
+    pub fn is_error(&self) -> bool {
+        self.role == "assistant" && self.content.starts_with(ERROR_PREFIX)
+    }
 }
 
 /// Chat history container.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ChatHistory {
     /// Version for future schema migrations.
+    #[serde(default)]
     pub version: u32,
     /// List of chat messages.
+    #[serde(default)]
     pub messages: Vec<HistoryMessage>,
+    /// Tokens used over the whole conversation.
+    #[serde(default)]
+    pub tokens: TokenUsage,
+    /// Conversation title, generated from the first user message.
+    #[serde(default)]
+    pub title: String,
+    /// Unix timestamp (seconds) when the conversation was started.
+    #[serde(default)]
+    pub created_at: u64,
+    /// Unix timestamp (seconds) of the last save.
+    #[serde(default)]
+    pub updated_at: u64,
+    /// Model the conversation uses; empty for the configured default.
+    #[serde(default)]
+    pub model: String,
+    /// Persona whose prompt the conversation uses; empty for the
+    /// configured system prompt.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub persona: String,
+    /// Text typed but not yet sent.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub draft: String,
+}
+
+/// A saved conversation, without its messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conversation {
+    /// Unique id, also the file name.
+    pub id: u64,
+    /// Title shown in the picker; empty until the first message.
+    pub title: String,
+    /// Unix timestamp (seconds) when the conversation was started.
+    pub created_at: u64,
+    /// Unix timestamp (seconds) of the last save.
+    pub updated_at: u64,
+    /// Model the conversation uses; empty for the configured default.
+    pub model: String,
+    /// Persona whose prompt the conversation uses; empty for the
+    /// configured system prompt.
+    pub persona: String,
+    /// Text typed but not yet sent, restored when the conversation is shown.
+    pub draft: String,
+}
+
+impl Conversation {
+    /// Start a new, untitled conversation.
+    pub fn new() -> Self {
+        let now = unix_now();
+        Self {
+            id: now.as_millis() as u64,
+            title: String::new(),
+            created_at: now.as_secs(),
+            updated_at: now.as_secs(),
+            model: String::new(),
+            persona: String::new(),
+            draft: String::new(),
+        }
+    }
+
+    /// Title for display, with a fallback for untitled conversations.
+    pub fn display_title(&self) -> &str {
+        if self.title.is_empty() {
+            "New conversation"
+        } else {
+            &self.title
+        }
+    }
 }
 
 impl ChatHistory {
-    /// Current history format version.
-    const CURRENT_VERSION: u32 = 1;
+    /// Current history format version. Version 2 added message timestamps.
+    const CURRENT_VERSION: u32 = 2;
 
     /// Create a new empty history.
     pub fn new() -> Self {
         Self {
             version: Self::CURRENT_VERSION,
             messages: Vec::new(),
+            tokens: TokenUsage::default(),
+            ..Default::default()
         }
     }
 
     /// Create history from existing messages.
-    pub fn from_messages(messages: Vec<(String, String)>) -> Self {
+    pub fn from_messages(messages: Vec<HistoryMessage>) -> Self {
         let history_messages: Vec<HistoryMessage> = messages
             .into_iter()
-            .map(|(role, content)| HistoryMessage { role, content })
+            .map(|message| HistoryMessage {
+                content: sanitize_content(&message.content),
+                ..message
+            })
             .collect();
 
         Self {
             version: Self::CURRENT_VERSION,
             messages: history_messages,
+            tokens: TokenUsage::default(),
+            ..Default::default()
         }
     }
 
-    /// Convert history to message tuples for the app.
-    pub fn to_messages(&self) -> Vec<(String, String)> {
-        self.messages
-            .iter()
-            .map(|m| (m.role.clone(), m.content.clone()))
-            .collect()
+    /// Messages for the app.
+    pub fn to_messages(&self) -> Vec<HistoryMessage> {
+        self.messages.clone()
     }
 
-    /// Trim history to max size, keeping most recent messages.
-    pub fn trim_to_limit(&mut self) {
-        if self.messages.len() > MAX_HISTORY_SIZE {
-            let excess = self.messages.len() - MAX_HISTORY_SIZE;
+    /// Trim history to `limit` messages, keeping the most recent ones.
+    pub fn trim_to_limit(&mut self, limit: usize) {
+        if self.messages.len() > limit {
+            let excess = self.messages.len() - limit;
             self.messages.drain(0..excess);
         }
     }
 }
 
-/// Get the path to the history file.
-fn history_file_path() -> Option<PathBuf> {
+/// Strip characters that don't belong in a persisted transcript.
+///
+/// Rust strings are always valid UTF-8, but lossy captures can carry NULs and
+/// other control characters that confuse tools reading the file. Newlines,
+/// tabs and everything printable (emoji, combining marks, U+FFFD) are kept.
+fn sanitize_content(content: &str) -> String {
+    content
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t' | '\r'))
+        .collect()
+}
+
+/// Parse a history file, salvaging what we can from unexpected content.
+///
+/// Invalid UTF-8 is replaced rather than rejected, unknown fields are
+/// ignored, and individual malformed messages are skipped instead of
+/// discarding the whole history.
+fn parse_history(bytes: &[u8]) -> ChatHistory {
+    let text = String::from_utf8_lossy(bytes);
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) else {
+        return ChatHistory::new();
+    };
+
+    if let Ok(history) = serde_json::from_value::<ChatHistory>(value.clone()) {
+        return history;
+    }
+
+    let messages = value
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| serde_json::from_value(entry.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    ChatHistory {
+        version: ChatHistory::CURRENT_VERSION,
+        messages,
+        tokens: value
+            .get("tokens")
+            .and_then(|t| serde_json::from_value(t.clone()).ok())
+            .unwrap_or_default(),
+        title: value
+            .get("title")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        created_at: value
+            .get("created_at")
+            .and_then(|t| t.as_u64())
+            .unwrap_or_default(),
+        updated_at: value
+            .get("updated_at")
+            .and_then(|t| t.as_u64())
+            .unwrap_or_default(),
+        model: value
+            .get("model")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        persona: value
+            .get("persona")
+            .and_then(|p| p.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        draft: value
+            .get("draft")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Get the applet's data directory.
+fn data_dir() -> Option<PathBuf> {
     // Use XDG_DATA_HOME or ~/.local/share
     let data_dir = std::env::var("XDG_DATA_HOME")
         .map(PathBuf::from)
@@ -82,13 +281,162 @@ fn history_file_path() -> Option<PathBuf> {
             PathBuf::from(home).join(".local/share")
         });
 
-    let app_dir = data_dir.join("cosmic-applet-ollama");
-    Some(app_dir.join("history.json"))
+    Some(data_dir.join("cosmic-applet-ollama"))
+}
+
+/// Get the user's Downloads folder, used when no save dialog is available.
+pub fn downloads_dir() -> Option<PathBuf> {
+    let home = std::env::var("HOME").ok()?;
+    Some(PathBuf::from(home).join("Downloads"))
+}
+
+/// Get the path to the single-conversation history file used before
+/// conversations were split into separate files.
+fn legacy_history_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("history.json"))
+}
+
+/// Get the directory holding one file per conversation.
+fn conversations_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("conversations"))
+}
+
+/// Directory attached images are kept in, so history files stay small.
+fn images_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("images"))
+}
+
+/// Where a stored image lives.
+pub fn image_path(name: &str) -> Option<PathBuf> {
+    images_dir().map(|dir| dir.join(name))
+}
+
+/// Keep a base64 JPEG attached to a message, returning the name the
+/// message refers to it by. The same image is only stored once.
+pub fn store_image(data: &str) -> io::Result<String> {
+    let Some(dir) = images_dir() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not determine image path",
+        ));
+    };
+    store_image_in(&dir, data)
+}
+
+fn store_image_in(dir: &Path, data: &str) -> io::Result<String> {
+    let bytes = BASE64_STANDARD
+        .decode(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let name = format!("{:016x}.jpg", hasher.finish());
+
+    let path = dir.join(&name);
+    if !path.exists() {
+        fs::create_dir_all(dir)?;
+        write_atomic(&path, &bytes)?;
+    }
+    Ok(name)
+}
+
+/// A stored image, base64-encoded for the chat API.
+pub fn load_image(name: &str) -> Option<String> {
+    let bytes = fs::read(image_path(name)?).ok()?;
+    Some(BASE64_STANDARD.encode(bytes))
+}
+
+/// Move images that older versions saved inline into the image directory.
+///
+/// Base64 has no dots and stored names always do, so the two can't be
+/// confused. An image that can't be stored is left inline.
+fn migrate_images(history: &mut ChatHistory, dir: &Path) {
+    let inline = history
+        .messages
+        .iter_mut()
+        .flat_map(|m| &mut m.images)
+        .filter(|image| !image.contains('.'));
+    for image in inline {
+        if let Ok(name) = store_image_in(dir, image) {
+            *image = name;
+        }
+    }
+}
+
+/// Get the path to a conversation's file.
+fn conversation_path(id: u64) -> Option<PathBuf> {
+    conversations_dir().map(|dir| dir.join(format!("{}.json", id)))
+}
+
+/// Time since the unix epoch.
+pub fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Move a legacy `history.json` into its own conversation file.
+fn migrate_legacy_history() -> io::Result<()> {
+    let Some(legacy) = legacy_history_path().filter(|p| p.exists()) else {
+        return Ok(());
+    };
+
+    let history = parse_history(&fs::read(&legacy)?);
+    if !history.messages.is_empty() {
+        let conversation = Conversation::new();
+        save_history(&conversation, &history.to_messages(), history.tokens, None)?;
+    }
+    fs::remove_file(legacy)
+}
+
+/// List saved conversations, most recently updated first.
+pub fn list_conversations() -> Vec<Conversation> {
+    let _ = migrate_legacy_history();
+
+    let Some(entries) = conversations_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut conversations: Vec<Conversation> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let id = path.file_stem()?.to_str()?.parse().ok()?;
+            let history = parse_history(&fs::read(&path).ok()?);
+            Some(Conversation {
+                id,
+                title: history.title,
+                created_at: history.created_at,
+                updated_at: history.updated_at,
+                model: history.model,
+                persona: history.persona,
+                draft: history.draft,
+            })
+        })
+        .collect();
+
+    conversations.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+    conversations
+}
+
+/// Title for a conversation: the first line of its first user message.
+pub fn conversation_title(messages: &[HistoryMessage]) -> String {
+    const MAX_TITLE_CHARS: usize = 40;
+
+    let Some(message) = messages.iter().find(|m| m.role == "user") else {
+        return String::new();
+    };
+    let line = message.content.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > MAX_TITLE_CHARS {
+        let truncated: String = line.chars().take(MAX_TITLE_CHARS - 1).collect();
+        format!("{}…", truncated.trim_end())
+    } else {
+        line.to_string()
+    }
 }
 
-/// Load chat history from disk.
-pub fn load_history() -> ChatHistory {
-    let Some(path) = history_file_path() else {
+/// Load a conversation's history from disk.
+pub fn load_history(id: u64) -> ChatHistory {
+    let Some(path) = conversation_path(id) else {
         return ChatHistory::new();
     };
 
@@ -96,21 +444,36 @@ pub fn load_history() -> ChatHistory {
         return ChatHistory::new();
     }
 
-    let file = match fs::File::open(&path) {
-        Ok(f) => f,
+    let mut history = match fs::read(&path) {
+        Ok(bytes) => parse_history(&bytes),
         Err(_) => return ChatHistory::new(),
     };
-
-    let reader = BufReader::new(file);
-    match serde_json::from_reader(reader) {
-        Ok(history) => history,
-        Err(_) => ChatHistory::new(),
+    if let Some(dir) = images_dir() {
+        migrate_images(&mut history, &dir);
     }
+    history
 }
 
-/// Save chat history to disk.
-pub fn save_history(messages: &[(String, String)]) -> io::Result<()> {
-    let Some(path) = history_file_path() else {
+/// The most recent `limit` messages, which make up the request payload.
+///
+/// Display and on-disk retention are independent of this window.
+pub fn payload_window(messages: &[HistoryMessage], limit: usize) -> &[HistoryMessage] {
+    let start = messages.len().saturating_sub(limit);
+    &messages[start..]
+}
+
+/// Save a conversation's history to disk.
+///
+/// With `keep` set only that many of the most recent messages are kept;
+/// otherwise the full scrollback is written. The token counter always
+/// covers the whole conversation.
+pub fn save_history(
+    conversation: &Conversation,
+    messages: &[HistoryMessage],
+    tokens: TokenUsage,
+    keep: Option<usize>,
+) -> io::Result<()> {
+    let Some(path) = conversation_path(conversation.id) else {
         return Err(io::Error::new(
             io::ErrorKind::NotFound,
             "Could not determine history path",
@@ -123,18 +486,310 @@ pub fn save_history(messages: &[(String, String)]) -> io::Result<()> {
     }
 
     let mut history = ChatHistory::from_messages(messages.to_vec());
-    history.trim_to_limit();
+    history.tokens = tokens;
+    history.title = conversation.title.clone();
+    history.created_at = conversation.created_at;
+    history.updated_at = unix_now().as_secs();
+    history.model = conversation.model.clone();
+    history.persona = conversation.persona.clone();
+    history.draft = conversation.draft.clone();
+    if let Some(limit) = keep {
+        history.trim_to_limit(limit);
+    }
 
-    let file = fs::File::create(&path)?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, &history)?;
+    write_atomic(&path, &serde_json::to_vec_pretty(&history)?)
+}
 
-    Ok(())
+/// Temporary file a save is written to before replacing `path`.
+///
+/// It lives in the same directory so the rename stays on one filesystem,
+/// and carries the process id so two running instances don't share one.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
 }
 
-/// Clear saved history from disk.
-pub fn clear_history() -> io::Result<()> {
-    let Some(path) = history_file_path() else {
+/// Replace `path` with `contents` without ever leaving it half-written.
+///
+/// The data is written and synced to a temporary file which is then
+/// renamed over the target, so a crash mid-save keeps the previous file.
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let result = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    match result.and_then(|()| fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// A single line in the append-only transcript log.
+#[derive(Debug, Clone, Serialize)]
+struct TranscriptEntry<'a> {
+    /// Unix timestamp in seconds.
+    timestamp: u64,
+    role: &'a str,
+    model: &'a str,
+    content: &'a str,
+}
+
+/// Append completed messages to a JSONL transcript log.
+///
+/// Unlike the history file this is never trimmed or cleared; it is a
+/// personal archive of every conversation.
+pub fn append_transcript(path: &Path, model: &str, messages: &[HistoryMessage]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let now = unix_now().as_secs();
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut writer = BufWriter::new(file);
+    for message in messages {
+        let entry = TranscriptEntry {
+            timestamp: if message.timestamp > 0 {
+                message.timestamp
+            } else {
+                now
+            },
+            role: &message.role,
+            model: if message.model.is_empty() {
+                model
+            } else {
+                &message.model
+            },
+            content: &message.content,
+        };
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// A message line read back from a JSONL transcript; extra fields such as
+/// the model are ignored.
+#[derive(Debug, Clone, Deserialize)]
+struct ImportedEntry {
+    role: String,
+    content: String,
+    #[serde(default)]
+    timestamp: u64,
+}
+
+/// Import a conversation from a JSON history, JSONL transcript or markdown
+/// file.
+///
+/// The format is picked from the file extension. A JSON history keeps its
+/// title, model and token count and is upgraded to the current version;
+/// one that isn't valid JSON or comes from a newer version is rejected.
+/// Malformed entries and roles other than user/assistant are skipped; a
+/// file with nothing usable is an error.
+pub fn import_from_path(path: &Path) -> Result<ChatHistory, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Could not read file: {}", e))?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut history = match extension.as_str() {
+        "md" | "markdown" => ChatHistory::from_messages(parse_markdown(&text)),
+        "jsonl" => ChatHistory::from_messages(parse_jsonl(&text)),
+        _ => parse_import_json(&text)?,
+    };
+
+    history.messages.retain(|m| {
+        matches!(m.role.as_str(), "user" | "assistant") && !m.content.trim().is_empty()
+    });
+    for message in &mut history.messages {
+        message.content = sanitize_content(&message.content);
+    }
+
+    if history.messages.is_empty() {
+        return Err("No messages found in file".to_string());
+    }
+    if let Some(dir) = images_dir() {
+        migrate_images(&mut history, &dir);
+    }
+    Ok(history)
+}
+
+/// Parse an exported JSON history, refusing what `parse_history` would
+/// quietly turn into an empty one.
+fn parse_import_json(text: &str) -> Result<ChatHistory, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("Not a valid history file: {}", e))?;
+    if !value.get("messages").is_some_and(|m| m.is_array()) {
+        return Err("Not a history file: no messages list".to_string());
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version > u64::from(ChatHistory::CURRENT_VERSION) {
+        return Err(format!(
+            "History version {} is newer than this applet supports",
+            version
+        ));
+    }
+
+    // Older versions only lack fields that default sensibly
+    let mut history = parse_history(text.as_bytes());
+    history.version = ChatHistory::CURRENT_VERSION;
+    Ok(history)
+}
+
+/// Parse one JSON message per line, skipping lines that don't parse.
+fn parse_jsonl(text: &str) -> Vec<HistoryMessage> {
+    text.lines()
+        .filter_map(|line| serde_json::from_str::<ImportedEntry>(line).ok())
+        .map(|entry| HistoryMessage {
+            role: entry.role,
+            content: entry.content,
+            timestamp: entry.timestamp,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Heading that starts a message in a markdown transcript.
+fn markdown_heading(role: &str) -> &'static str {
+    match role {
+        "user" => "## User",
+        _ => "## Assistant",
+    }
+}
+
+/// Heading that starts a message in an exported conversation, matching the
+/// labels shown in the chat.
+fn export_heading(role: &str) -> &'static str {
+    match role {
+        "user" => "### You",
+        "system" => "### System",
+        _ => "### AI",
+    }
+}
+
+/// Render a conversation as a Markdown document, e.g. for a bug report.
+///
+/// Message content is copied verbatim, so code fences survive. Exports can
+/// be imported again.
+pub fn export_markdown(messages: &[HistoryMessage]) -> String {
+    export_markdown_at(messages, unix_now().as_secs())
+}
+
+fn export_markdown_at(messages: &[HistoryMessage], timestamp: u64) -> String {
+    let mut document = String::from("# Chat export\n\n");
+    let _ = writeln!(document, "Exported {}", format_timestamp(timestamp));
+    for message in messages {
+        let _ = writeln!(
+            document,
+            "\n{}\n\n{}",
+            export_heading(&message.role),
+            message.content.trim_end()
+        );
+    }
+    document
+}
+
+/// How long ago a message was sent, e.g. "just now", "5m ago" or "3d ago";
+/// anything older than a week shows its date. `None` for messages without
+/// a timestamp.
+pub fn relative_time(timestamp: u64) -> Option<String> {
+    relative_time_at(timestamp, unix_now().as_secs())
+}
+
+fn relative_time_at(timestamp: u64, now: u64) -> Option<String> {
+    if timestamp == 0 {
+        return None;
+    }
+    let elapsed = now.saturating_sub(timestamp);
+    Some(match elapsed {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", elapsed / 60),
+        3_600..86_400 => format!("{}h ago", elapsed / 3_600),
+        86_400..604_800 => format!("{}d ago", elapsed / 86_400),
+        _ => format_timestamp(timestamp)[..10].to_string(),
+    })
+}
+
+/// Format a unix timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = timestamp / 86_400;
+    let minutes = (timestamp % 86_400) / 60;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Parse a markdown transcript made of `## User` / `## Assistant` sections
+/// (or the `### You` / `### AI` headings of an export).
+///
+/// Anything before the first heading (such as a title) is ignored, and so
+/// are headings inside code fences, which belong to the message.
+fn parse_markdown(text: &str) -> Vec<HistoryMessage> {
+    let mut messages: Vec<HistoryMessage> = Vec::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        let line_trimmed = line.trim_end();
+        let fence = line_trimmed.trim_start();
+        if fence.starts_with("```") || fence.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let role = ["user", "assistant"].into_iter().find(|role| {
+            !in_fence
+                && (line_trimmed.eq_ignore_ascii_case(markdown_heading(role))
+                    || line_trimmed.eq_ignore_ascii_case(export_heading(role)))
+        });
+
+        match (role, messages.last_mut()) {
+            (Some(role), _) => messages.push(HistoryMessage {
+                role: role.to_string(),
+                ..Default::default()
+            }),
+            (None, Some(message)) => {
+                message.content.push_str(line);
+                message.content.push('\n');
+            }
+            (None, None) => {}
+        }
+    }
+
+    for message in &mut messages {
+        message.content = message.content.trim().to_string();
+    }
+    messages
+}
+
+/// Delete a conversation's saved history from disk.
+pub fn clear_history(id: u64) -> io::Result<()> {
+    let Some(path) = conversation_path(id) else {
         return Ok(());
     };
 
@@ -149,36 +804,448 @@ pub fn clear_history() -> io::Result<()> {
 mod tests {
     use super::*;
 
+    const LIMIT: usize = 100;
+
+    fn msg(role: &str, content: impl Into<String>) -> HistoryMessage {
+        HistoryMessage {
+            role: role.to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_history_roundtrip() {
+        let messages = vec![msg("user", "Hello"), msg("assistant", "Hi there!")];
+
+        let history = ChatHistory::from_messages(messages.clone());
+        let restored = history.to_messages();
+
+        assert_eq!(restored, messages);
+    }
+
+    #[test]
+    fn test_tricky_content_roundtrip() {
         let messages = vec![
-            ("user".to_string(), "Hello".to_string()),
-            ("assistant".to_string(), "Hi there!".to_string()),
+            msg("user", "Emoji 🦀🚀 and flags 🇳🇿"),
+            msg("assistant", "Combining: e\u{301} n\u{303}"),
+            msg("user", "Tabs\tand\nnewlines\r\n"),
+            msg("assistant", "Replacement \u{FFFD} char"),
         ];
 
         let history = ChatHistory::from_messages(messages.clone());
-        let restored = history.to_messages();
+        let bytes = serde_json::to_vec_pretty(&history).unwrap();
+        let restored = parse_history(&bytes).to_messages();
 
         assert_eq!(restored, messages);
     }
 
+    #[test]
+    fn test_nulls_are_stripped() {
+        let messages = vec![msg("user", "nul\0byte\u{7}")];
+
+        let history = ChatHistory::from_messages(messages);
+        let bytes = serde_json::to_vec_pretty(&history).unwrap();
+        let restored = parse_history(&bytes);
+
+        assert_eq!(restored.messages[0].content, "nulbyte");
+    }
+
+    #[test]
+    fn test_load_tolerates_unexpected_shape() {
+        let json = br#"{
+            "version": 1,
+            "extra": "ignored",
+            "messages": [
+                {"role": "user", "content": "kept", "timestamp": 5},
+                {"role": "assistant", "content": 42},
+                {"role": "assistant", "content": "also kept"}
+            ]
+        }"#;
+
+        let restored = parse_history(json).to_messages();
+
+        assert_eq!(
+            restored,
+            vec![
+                HistoryMessage {
+                    timestamp: 5,
+                    ..msg("user", "kept")
+                },
+                msg("assistant", "also kept"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_bubbles() {
+        assert!(HistoryMessage::error("connection refused").is_error());
+        assert!(!msg("assistant", "No error here").is_error());
+        assert!(!msg("user", "Error: what does this mean?").is_error());
+    }
+
+    #[test]
+    fn test_answer_model_persists() {
+        let answer = HistoryMessage {
+            model: "llama3.2".to_string(),
+            ..msg("assistant", "Hi there!")
+        };
+        let bytes =
+            serde_json::to_vec(&ChatHistory::from_messages(vec![msg("user", "Hi"), answer]))
+                .unwrap();
+        let restored = parse_history(&bytes).to_messages();
+        assert_eq!(restored[0].model, "");
+        assert_eq!(restored[1].model, "llama3.2");
+
+        // Answers saved before the model was recorded have none
+        let json = br#"{"version": 1, "messages": [{"role": "assistant", "content": "Old"}]}"#;
+        assert_eq!(parse_history(json).messages[0].model, "");
+    }
+
+    #[test]
+    fn test_token_counter_persists() {
+        let mut history = ChatHistory::from_messages(vec![msg("user", "Hi")]);
+        history.tokens = TokenUsage {
+            prompt: 120,
+            completion: 30,
+        };
+        let bytes = serde_json::to_vec_pretty(&history).unwrap();
+        assert_eq!(parse_history(&bytes).tokens.total(), 150);
+
+        // Histories saved before the counter existed start from zero
+        let old = br#"{"version":1,"messages":[]}"#;
+        assert_eq!(parse_history(old).tokens, TokenUsage::default());
+
+        // Each answer keeps its own share (and speed), so dropping it can
+        // be undone
+        let answer = HistoryMessage {
+            usage: TokenUsage {
+                prompt: 20,
+                completion: 5,
+            },
+            stats: Some(GenerationStats {
+                tokens: 5,
+                tokens_per_sec: 38.5,
+            }),
+            ..msg("assistant", "Hello")
+        };
+        let history = ChatHistory::from_messages(vec![answer.clone()]);
+        let bytes = serde_json::to_vec_pretty(&history).unwrap();
+        assert_eq!(parse_history(&bytes).messages, vec![answer]);
+        let mut tokens = TokenUsage {
+            prompt: 10,
+            completion: 30,
+        };
+        tokens -= history.messages[0].usage;
+        assert_eq!(tokens.total(), 25);
+    }
+
+    #[test]
+    fn test_load_replaces_invalid_utf8() {
+        let mut bytes = br#"{"version":1,"messages":[{"role":"user","content":"bad "#.to_vec();
+        bytes.extend_from_slice(&[0xff, 0xfe]);
+        bytes.extend_from_slice(br#""}]}"#);
+
+        let restored = parse_history(&bytes);
+
+        assert_eq!(restored.messages.len(), 1);
+        assert!(restored.messages[0].content.starts_with("bad "));
+        assert!(restored.messages[0].content.contains('\u{FFFD}'));
+    }
+
+    #[test]
+    fn test_payload_window_keeps_recent() {
+        let messages: Vec<HistoryMessage> = (0..(LIMIT + 10))
+            .map(|i| msg("user", format!("Message {}", i)))
+            .collect();
+
+        let window = payload_window(&messages, LIMIT);
+        assert_eq!(window.len(), LIMIT);
+        assert_eq!(window[0].content, "Message 10");
+
+        let short = &messages[..3];
+        assert_eq!(payload_window(short, LIMIT), short);
+    }
+
+    /// An empty directory of its own for a test, so parallel runs don't
+    /// share files.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cosmic-applet-ollama-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_inline_images_move_to_files() {
+        let dir = test_dir("images");
+        let data = BASE64_STANDARD.encode(b"not really a jpeg");
+        let mut history = ChatHistory::from_messages(vec![HistoryMessage {
+            images: vec![data.clone()],
+            ..msg("user", "What's this?")
+        }]);
+
+        migrate_images(&mut history, &dir);
+        let name = history.messages[0].images[0].clone();
+        let stored = fs::read(dir.join(&name));
+        // Already stored images are left as they are
+        migrate_images(&mut history, &dir);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(name.ends_with(".jpg"));
+        assert_eq!(BASE64_STANDARD.encode(stored.unwrap()), data);
+        assert_eq!(history.messages[0].images, vec![name]);
+    }
+
+    #[test]
+    fn test_interrupted_save_keeps_history() {
+        let dir = test_dir("atomic");
+        let path = dir.join("history.json");
+        let history = ChatHistory::from_messages(vec![msg("user", "Keep me")]);
+        write_atomic(&path, &serde_json::to_vec_pretty(&history).unwrap()).unwrap();
+        assert!(!temp_path(&path).exists());
+
+        // A save that can't write its temp file fails without touching the
+        // saved history
+        fs::create_dir(temp_path(&path)).unwrap();
+        let failed = write_atomic(&path, b"{\"messages\": [{\"role\": \"us");
+
+        let loaded = parse_history(&fs::read(&path).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(failed.is_err());
+        assert_eq!(loaded.messages, history.messages);
+    }
+
+    #[test]
+    fn test_import_json_roundtrip() {
+        let messages = vec![msg("user", "Hello"), msg("assistant", "Hi there!")];
+        let dir = test_dir("import-json");
+        let path = dir.join("chat.json");
+        let history = ChatHistory::from_messages(messages.clone());
+        fs::write(&path, serde_json::to_vec_pretty(&history).unwrap()).unwrap();
+
+        let imported = import_from_path(&path);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.unwrap().messages, messages);
+    }
+
+    #[test]
+    fn test_import_rejects_bad_json() {
+        assert!(parse_import_json("{\"messages\": [{\"role\"").is_err());
+        assert!(parse_import_json("{\"model\": \"llama3\"}").is_err());
+        let newer = format!(
+            "{{\"version\": {}, \"messages\": []}}",
+            ChatHistory::CURRENT_VERSION + 1
+        );
+        assert!(parse_import_json(&newer).is_err());
+
+        // Version 1 files are upgraded on import
+        let old =
+            r#"{"version": 1, "title": "Old", "messages": [{"role": "user", "content": "Hi"}]}"#;
+        let history = parse_import_json(old).unwrap();
+        assert_eq!(history.version, ChatHistory::CURRENT_VERSION);
+        assert_eq!(history.title, "Old");
+        assert_eq!(history.messages, vec![msg("user", "Hi")]);
+    }
+
+    #[test]
+    fn test_import_transcript_roundtrip() {
+        let messages = vec![
+            HistoryMessage::new("user", "Hello"),
+            HistoryMessage::new("assistant", "Line one\nline two"),
+        ];
+        let dir = test_dir("import-transcript");
+        let path = dir.join("transcript.jsonl");
+        append_transcript(&path, "llama3.2:3b", &messages).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        let imported = import_from_path(&path);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.unwrap().messages, messages);
+    }
+
+    #[test]
+    fn test_parse_markdown() {
+        let markdown = "# Chat export\n\n## User\n\nHow do I list files?\n\n\
+            ## Assistant\n\nUse:\n\n```\nls -la\n```\n";
+
+        assert_eq!(
+            parse_markdown(markdown),
+            vec![
+                msg("user", "How do I list files?"),
+                msg("assistant", "Use:\n\n```\nls -la\n```"),
+            ]
+        );
+
+        // A heading inside a fence is part of the message
+        let markdown = "### You\n\nWhat does this print?\n\n```md\n### You\n```\n\n\
+            ### AI\n\nA heading.\n";
+        assert_eq!(
+            parse_markdown(markdown),
+            vec![
+                msg("user", "What does this print?\n\n```md\n### You\n```"),
+                msg("assistant", "A heading."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_markdown() {
+        let messages = vec![
+            msg("user", "How do I list files?"),
+            msg("assistant", "Use:\n\n```bash\nls -la\n```\n"),
+        ];
+
+        assert_eq!(
+            export_markdown_at(&messages, 1_700_000_000),
+            "# Chat export\n\nExported 2023-11-14 22:13 UTC\n\
+            \n### You\n\nHow do I list files?\n\
+            \n### AI\n\nUse:\n\n```bash\nls -la\n```\n"
+        );
+
+        // Exports can be imported again
+        assert_eq!(
+            parse_markdown(&export_markdown(&messages)),
+            vec![
+                messages[0].clone(),
+                msg("assistant", "Use:\n\n```bash\nls -la\n```"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_empty_history() {
+        assert_eq!(
+            export_markdown_at(&[], 0),
+            "# Chat export\n\nExported 1970-01-01 00:00 UTC\n"
+        );
+    }
+
+    #[test]
+    fn test_conversation_title() {
+        let messages = vec![
+            msg("assistant", "Hi!"),
+            msg(
+                "user",
+                "How do I change the panel size in COSMIC settings?\nDetails...",
+            ),
+        ];
+
+        assert_eq!(
+            conversation_title(&messages),
+            "How do I change the panel size in COSMI…"
+        );
+        assert_eq!(conversation_title(&messages[..1]), "");
+    }
+
+    #[test]
+    fn test_conversation_metadata_roundtrip() {
+        let mut history = ChatHistory::from_messages(vec![msg("user", "Hi")]);
+        history.title = "Hi".to_string();
+        history.created_at = 1_700_000_000;
+        history.model = "qwen3:4b".to_string();
+        history.persona = "Shell expert".to_string();
+
+        let bytes = serde_json::to_vec_pretty(&history).unwrap();
+        let restored = parse_history(&bytes);
+
+        assert_eq!(restored.title, "Hi");
+        assert_eq!(restored.created_at, 1_700_000_000);
+        assert_eq!(restored.model, "qwen3:4b");
+        assert_eq!(restored.persona, "Shell expert");
+
+        // Conversations saved before models were tracked use the default
+        let restored = parse_history(br#"{"messages": [], "title": "Old"}"#);
+        assert_eq!(restored.model, "");
+        assert_eq!(restored.persona, "");
+    }
+
+    #[test]
+    fn test_draft_roundtrip() {
+        let mut history = ChatHistory::from_messages(vec![msg("user", "Hi")]);
+        let json = serde_json::to_string(&history).unwrap();
+        assert!(!json.contains("draft"));
+
+        history.draft = "Half a quest".to_string();
+        let bytes = serde_json::to_vec(&history).unwrap();
+        assert_eq!(parse_history(&bytes).draft, "Half a quest");
+    }
+
+    #[test]
+    fn test_version_1_history_without_timestamps() {
+        let json = br#"{
+            "version": 1,
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"}
+            ]
+        }"#;
+
+        let history = parse_history(json);
+        assert_eq!(history.version, 1);
+        assert_eq!(
+            history.to_messages(),
+            vec![msg("user", "Hi"), msg("assistant", "Hello!")]
+        );
+        assert_eq!(relative_time(history.messages[0].timestamp), None);
+
+        // Saving upgrades the file and keeps timestamps from then on
+        let mut messages = history.to_messages();
+        messages.push(HistoryMessage {
+            timestamp: 1_700_000_000,
+            ..msg("user", "Thanks")
+        });
+        let bytes = serde_json::to_vec(&ChatHistory::from_messages(messages)).unwrap();
+        let upgraded = parse_history(&bytes);
+        assert_eq!(upgraded.version, ChatHistory::CURRENT_VERSION);
+        assert_eq!(upgraded.messages[2].timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = 1_700_000_000;
+        assert_eq!(relative_time_at(now - 5, now).as_deref(), Some("just now"));
+        assert_eq!(relative_time_at(now - 150, now).as_deref(), Some("2m ago"));
+        assert_eq!(
+            relative_time_at(now - 7_200, now).as_deref(),
+            Some("2h ago")
+        );
+        assert_eq!(
+            relative_time_at(now - 3 * 86_400, now).as_deref(),
+            Some("3d ago")
+        );
+        assert_eq!(
+            relative_time_at(now - 30 * 86_400, now).as_deref(),
+            Some("2023-10-15")
+        );
+    }
+
     #[test]
     fn test_trim_to_limit() {
         let mut history = ChatHistory::new();
-        for i in 0..(MAX_HISTORY_SIZE + 50) {
-            history.messages.push(HistoryMessage {
-                role: "user".to_string(),
-                content: format!("Message {}", i),
-            });
+        for i in 0..(LIMIT + 50) {
+            history.messages.push(msg("user", format!("Message {}", i)));
         }
 
-        history.trim_to_limit();
-        assert_eq!(history.messages.len(), MAX_HISTORY_SIZE);
+        history.trim_to_limit(LIMIT);
+        assert_eq!(history.messages.len(), LIMIT);
 
         // Should keep the most recent messages
         assert_eq!(
             history.messages.last().unwrap().content,
-            format!("Message {}", MAX_HISTORY_SIZE + 49)
+            format!("Message {}", LIMIT + 49)
         );
     }
 }
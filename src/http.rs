@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0
+
+//! Settings shared by every outbound HTTP request.
+//!
+//! The Ollama client and web search both build their clients here, so a
+//! configured proxy applies to all of them. Without one, reqwest picks up
+//! the standard `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` variables itself.
+
+use std::sync::{PoisonError, RwLock};
+
+/// Proxy all requests go through, if one is configured.
+static PROXY: RwLock<Option<reqwest::Proxy>> = RwLock::new(None);
+
+/// Send all requests through `url` from now on, or go back to the
+/// environment's proxy settings with `None`.
+///
+/// An invalid URL is reported and leaves no proxy set.
+pub fn set_proxy(url: Option<&str>) -> Result<(), String> {
+    let proxy = parse_proxy(url);
+    *PROXY.write().unwrap_or_else(PoisonError::into_inner) = proxy.clone().ok().flatten();
+    proxy.map(|_| ())
+}
+
+/// The proxy for a configured URL; blank means none. Hosts in `NO_PROXY`
+/// still bypass it.
+fn parse_proxy(url: Option<&str>) -> Result<Option<reqwest::Proxy>, String> {
+    let Some(url) = url.map(str::trim).filter(|url| !url.is_empty()) else {
+        return Ok(None);
+    };
+    reqwest::Proxy::all(url)
+        .map(|proxy| Some(proxy.no_proxy(reqwest::NoProxy::from_env())))
+        .map_err(|e| format!("Invalid proxy URL {}: {}", url, e))
+}
+
+/// A client builder with the configured proxy applied.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    match PROXY.read().unwrap_or_else(PoisonError::into_inner).clone() {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proxy() {
+        assert!(parse_proxy(None).unwrap().is_none());
+        assert!(parse_proxy(Some("  ")).unwrap().is_none());
+        assert!(
+            parse_proxy(Some("http://proxy.example:3128"))
+                .unwrap()
+                .is_some()
+        );
+        assert!(parse_proxy(Some("not a url")).is_err());
+    }
+}
@@ -3,13 +3,94 @@
 mod app;
 mod config;
 mod context;
+mod dbus;
+mod highlight;
 mod history;
+mod http;
 mod i18n;
+mod markdown;
 mod ollama;
 mod web;
 
+/// A question for the running applet, from `--ask "question" [--send]`.
+#[derive(Debug, PartialEq, Eq)]
+struct AskArgs {
+    query: String,
+    /// Send the question right away instead of leaving it in the input.
+    submit: bool,
+}
+
+/// Read `--ask` from the command line; `None` when it isn't there. The
+/// question must follow it and can't be empty or another option.
+fn parse_ask(args: &[String]) -> Option<Result<AskArgs, String>> {
+    let position = args.iter().position(|arg| arg == "--ask")?;
+    let query = args
+        .get(position + 1)
+        .filter(|query| !query.starts_with("--") && !query.trim().is_empty());
+    let Some(query) = query else {
+        return Some(Err(
+            "--ask needs a question, e.g. --ask \"Why is my fan loud?\"".to_string(),
+        ));
+    };
+    Some(Ok(AskArgs {
+        query: query.clone(),
+        submit: args.iter().any(|arg| arg == "--send"),
+    }))
+}
+
 fn main() -> cosmic::iced::Result {
+    // `--ask "question" [--send]` hands the question to the running applet
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(ask) = parse_ask(&args) {
+        let result = ask.and_then(|ask| {
+            tokio::runtime::Runtime::new()
+                .map_err(|e| e.to_string())
+                .and_then(|runtime| runtime.block_on(dbus::ask(&ask.query, ask.submit)))
+        });
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let requested_languages = i18n_embed::DesktopLanguageRequester::requested_languages();
     i18n::init(&requested_languages);
     cosmic::applet::run::<app::AppModel>(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_ask() {
+        assert_eq!(parse_ask(&args(&[])), None);
+        assert_eq!(
+            parse_ask(&args(&["--ask", "Why is my fan loud?"])),
+            Some(Ok(AskArgs {
+                query: "Why is my fan loud?".to_string(),
+                submit: false,
+            }))
+        );
+        assert_eq!(
+            parse_ask(&args(&["--send", "--ask", "Hi"])),
+            Some(Ok(AskArgs {
+                query: "Hi".to_string(),
+                submit: true,
+            }))
+        );
+
+        // The question can't be missing, blank or another option
+        assert!(matches!(parse_ask(&args(&["--ask"])), Some(Err(_))));
+        assert!(matches!(parse_ask(&args(&["--ask", "  "])), Some(Err(_))));
+        assert!(matches!(
+            parse_ask(&args(&["--ask", "--send"])),
+            Some(Err(_))
+        ));
+    }
+}
@@ -0,0 +1,386 @@
+// SPDX-License-Identifier: GPL-3.0
+
+//! Minimal markdown parsing for assistant messages.
+//!
+//! Covers what models actually emit in a chat panel: fenced code blocks,
+//! headings, bullet and numbered lists, and bold/italic/code inline spans.
+//! Parsing never fails; anything unrecognised (including markup that is
+//! still being streamed) is kept as literal text.
+
+/// Tags reasoning models wrap their thoughts in.
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// An assistant message split into its reasoning and its answer.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reply {
+    /// Text of all `<think>` blocks.
+    pub thoughts: String,
+    /// Everything outside them.
+    pub answer: String,
+    /// Whether the last `<think>` block hasn't been closed yet.
+    pub thinking: bool,
+}
+
+/// Separate `<think>` blocks from the answer.
+///
+/// Works on partially streamed text: an unclosed block counts as thoughts
+/// and a tag cut off at the end is held back until the rest arrives.
+pub fn split_thinking(text: &str) -> Reply {
+    let mut reply = Reply::default();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(THINK_OPEN) {
+        reply.answer.push_str(&rest[..start]);
+        let inner = &rest[start + THINK_OPEN.len()..];
+        let thought = match inner.find(THINK_CLOSE) {
+            Some(end) => {
+                rest = &inner[end + THINK_CLOSE.len()..];
+                &inner[..end]
+            }
+            None => {
+                reply.thinking = true;
+                rest = "";
+                trim_partial_tag(inner, THINK_CLOSE)
+            }
+        };
+        if !reply.thoughts.is_empty() {
+            reply.thoughts.push_str("\n\n");
+        }
+        reply.thoughts.push_str(thought.trim());
+    }
+    reply.answer.push_str(trim_partial_tag(rest, THINK_OPEN));
+
+    reply.thoughts = reply.thoughts.trim().to_string();
+    reply.answer = reply.answer.trim_start().to_string();
+    reply
+}
+
+/// Drop the start of `tag` (at least `<` plus one character) from the end
+/// of `text`.
+fn trim_partial_tag<'a>(text: &'a str, tag: &str) -> &'a str {
+    (2..tag.len())
+        .rev()
+        .find(|len| text.ends_with(&tag[..*len]))
+        .map_or(text, |len| &text[..text.len() - len])
+}
+
+/// A block-level element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    /// Running text, with single line breaks preserved.
+    Paragraph(Vec<Inline>),
+    /// A `#` heading with its level (1-6).
+    Heading(u8, Vec<Inline>),
+    /// A fenced code block. `closed` is false while the closing fence
+    /// hasn't arrived yet.
+    Code {
+        lang: String,
+        code: String,
+        closed: bool,
+    },
+    /// A list item; `number` is set for ordered lists.
+    ListItem {
+        number: Option<u64>,
+        content: Vec<Inline>,
+    },
+}
+
+/// A run of text sharing one style.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inline {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+}
+
+/// Parse markdown into blocks.
+pub fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    // Lines of the paragraph or list item being collected; list items
+    // carry their number (None for bullets)
+    let mut pending: Option<(Option<Option<u64>>, String)> = None;
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        if let Some(fence) = fence_marker(trimmed) {
+            flush(&mut blocks, pending.take());
+            let lang = trimmed[fence.len()..].trim().to_string();
+            let mut code = Vec::new();
+            let mut closed = false;
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with(fence) {
+                    closed = true;
+                    break;
+                }
+                code.push(line);
+            }
+            blocks.push(Block::Code {
+                lang,
+                code: code.join("\n"),
+                closed,
+            });
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            flush(&mut blocks, pending.take());
+            continue;
+        }
+
+        if let Some((level, heading)) = heading(trimmed) {
+            flush(&mut blocks, pending.take());
+            blocks.push(Block::Heading(level, parse_inline(heading)));
+            continue;
+        }
+
+        if let Some((number, item)) = list_item(trimmed) {
+            flush(&mut blocks, pending.take());
+            pending = Some((Some(number), item.to_string()));
+            continue;
+        }
+
+        // Continuation of the current paragraph or list item
+        match &mut pending {
+            Some((_, collected)) => {
+                collected.push('\n');
+                collected.push_str(trimmed);
+            }
+            None => pending = Some((None, trimmed.to_string())),
+        }
+    }
+
+    flush(&mut blocks, pending);
+    blocks
+}
+
+/// Turn a collected paragraph or list item into a block.
+fn flush(blocks: &mut Vec<Block>, pending: Option<(Option<Option<u64>>, String)>) {
+    match pending {
+        Some((Some(number), text)) => blocks.push(Block::ListItem {
+            number,
+            content: parse_inline(&text),
+        }),
+        Some((None, text)) => blocks.push(Block::Paragraph(parse_inline(&text))),
+        None => {}
+    }
+}
+
+/// The fence a line opens a code block with, if any.
+fn fence_marker(line: &str) -> Option<&'static str> {
+    ["```", "~~~"]
+        .into_iter()
+        .find(|fence| line.starts_with(fence))
+}
+
+/// Split a `## Heading` line into its level and text.
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if (1..=6).contains(&level) && rest.starts_with(' ') {
+        Some((level as u8, rest.trim()))
+    } else {
+        None
+    }
+}
+
+/// Split a `- item` or `1. item` line into its number and text.
+fn list_item(line: &str) -> Option<(Option<u64>, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((None, item.trim()));
+        }
+    }
+
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        let number = line[..digits].parse().ok()?;
+        return Some((Some(number), rest[2..].trim()));
+    }
+    None
+}
+
+/// Parse inline `code`, **bold** and *italic* spans.
+///
+/// Unclosed markers are kept as literal text.
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut spans = Vec::new();
+    parse_styled(text, false, false, &mut spans);
+    spans
+}
+
+fn parse_styled(text: &str, bold: bool, italic: bool, spans: &mut Vec<Inline>) {
+    let mut plain = String::new();
+    let mut rest = text;
+
+    let push = |spans: &mut Vec<Inline>, plain: &mut String| {
+        if !plain.is_empty() {
+            spans.push(Inline {
+                text: std::mem::take(plain),
+                bold,
+                italic,
+                code: false,
+            });
+        }
+    };
+
+    while let Some(c) = rest.chars().next() {
+        if c == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            push(spans, &mut plain);
+            spans.push(Inline {
+                text: rest[1..=end].to_string(),
+                bold,
+                italic,
+                code: true,
+            });
+            rest = &rest[end + 2..];
+            continue;
+        }
+
+        if let Some(inner) = rest.strip_prefix("**")
+            && let Some(end) = inner.find("**")
+            && end > 0
+        {
+            push(spans, &mut plain);
+            parse_styled(&inner[..end], true, italic, spans);
+            rest = &inner[end + 2..];
+            continue;
+        }
+
+        if c == '*'
+            && let Some(inner) = rest.strip_prefix('*')
+            && !inner.starts_with([' ', '*'])
+            && let Some(end) = inner.find('*')
+            && end > 0
+        {
+            push(spans, &mut plain);
+            parse_styled(&inner[..end], bold, true, spans);
+            rest = &inner[end + 1..];
+            continue;
+        }
+
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    push(spans, &mut plain);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Inline {
+        Inline {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let text = "# Title\n\nRun this:\n\n```bash\nls -la\n```\n\n- one\n- two\n\n1. first";
+
+        assert_eq!(
+            parse(text),
+            vec![
+                Block::Heading(1, vec![plain("Title")]),
+                Block::Paragraph(vec![plain("Run this:")]),
+                Block::Code {
+                    lang: "bash".to_string(),
+                    code: "ls -la".to_string(),
+                    closed: true,
+                },
+                Block::ListItem {
+                    number: None,
+                    content: vec![plain("one")],
+                },
+                Block::ListItem {
+                    number: None,
+                    content: vec![plain("two")],
+                },
+                Block::ListItem {
+                    number: Some(1),
+                    content: vec![plain("first")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_fence_while_streaming() {
+        let blocks = parse("Try:\n```\nsudo apt upd");
+
+        assert_eq!(
+            blocks[1],
+            Block::Code {
+                lang: String::new(),
+                code: "sudo apt upd".to_string(),
+                closed: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_inline_styles() {
+        let spans = parse_inline("Use **bold**, *italic* and `a*b`");
+
+        assert_eq!(
+            spans,
+            vec![
+                plain("Use "),
+                Inline {
+                    text: "bold".to_string(),
+                    bold: true,
+                    ..Default::default()
+                },
+                plain(", "),
+                Inline {
+                    text: "italic".to_string(),
+                    italic: true,
+                    ..Default::default()
+                },
+                plain(" and "),
+                Inline {
+                    text: "a*b".to_string(),
+                    code: true,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_thinking() {
+        let reply = split_thinking("<think>\nUser wants ls.\n</think>\n\nRun `ls`.");
+        assert_eq!(reply.thoughts, "User wants ls.");
+        assert_eq!(reply.answer, "Run `ls`.");
+        assert!(!reply.thinking);
+
+        // Mid-stream: the block is still open and its closing tag cut off
+        let reply = split_thinking("<think>Checking the logs</thi");
+        assert_eq!(reply.thoughts, "Checking the logs");
+        assert_eq!(reply.answer, "");
+        assert!(reply.thinking);
+
+        // An opening tag split across chunks isn't shown as text
+        assert_eq!(split_thinking("<thin").answer, "");
+        assert_eq!(split_thinking("a < b").answer, "a < b");
+        assert_eq!(split_thinking("Plain answer").thoughts, "");
+    }
+
+    #[test]
+    fn test_unclosed_markers_stay_literal() {
+        assert_eq!(
+            parse_inline("2 * 3 and **half"),
+            vec![plain("2 * 3 and **half")]
+        );
+        assert_eq!(parse_inline("`open"), vec![plain("`open")]);
+    }
+}
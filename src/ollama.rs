@@ -4,10 +4,14 @@
 //!
 //! Handles communication with the local Ollama server.
 
-use crate::config;
-use futures_util::StreamExt;
+use crate::{config, http};
+use futures_util::{Stream, StreamExt};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::AbortHandle;
 
 /// Default system prompt for the assistant.
 pub const DEFAULT_SYSTEM_PROMPT: &str = "\
@@ -28,20 +32,97 @@ GUIDELINES:
 - Provide specific commands when relevant.
 - For COSMIC questions without web results, suggest checking: https://system76.com/cosmic";
 
+/// Brevity directive appended to the system prompt in concise mode.
+pub const CONCISE_INSTRUCTION: &str =
+    "Answer in at most 3 sentences unless the user explicitly asks for detail.";
+
+/// Wire format spoken by the chat server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiFormat {
+    /// Native Ollama API (`/api/chat`, newline-delimited JSON).
+    #[default]
+    Ollama,
+    /// OpenAI-compatible API (`/v1/chat/completions`, server-sent events),
+    /// as served by LM Studio or llama.cpp.
+    OpenAI,
+}
+
+impl ApiFormat {
+    pub const ALL: [Self; 2] = [Self::Ollama, Self::OpenAI];
+
+    /// Name shown in the settings dropdown.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Ollama => "Ollama",
+            Self::OpenAI => "OpenAI-compatible",
+        }
+    }
+
+    /// Prefix for errors the server reports.
+    const fn error_label(self) -> &'static str {
+        match self {
+            Self::Ollama => "Ollama error",
+            Self::OpenAI => "Server error",
+        }
+    }
+}
+
+/// Rough number of characters per token, for turning a model's context
+/// length into a character budget.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Drop the oldest exchanges until the system prompt and messages fit in
+/// `budget_chars`.
+///
+/// The system prompt and the latest user message (with anything after it)
+/// are always kept, even when they alone exceed the budget.
+pub fn fit_to_budget(
+    system_prompt: &str,
+    mut messages: Vec<Message>,
+    budget_chars: usize,
+) -> Vec<Message> {
+    let keep_from = messages.iter().rposition(|m| m.role == "user").unwrap_or(0);
+    let mut total = system_prompt.chars().count()
+        + messages
+            .iter()
+            .map(|m| m.content.chars().count())
+            .sum::<usize>();
+
+    let mut start = 0;
+    while total > budget_chars && start < keep_from {
+        total -= messages[start].content.chars().count();
+        start += 1;
+    }
+    // Don't open on an answer whose question was dropped
+    while start < keep_from && messages[start].role != "user" {
+        start += 1;
+    }
+
+    messages.split_off(start)
+}
+
 /// A message in the Ollama chat format.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    /// Base64-encoded images for vision models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
 }
 
 impl Message {
-    pub fn system(content: impl Into<String>) -> Self {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
         Self {
-            role: "system".to_string(),
+            role: role.into(),
             content: content.into(),
+            images: None,
         }
     }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new("system", content)
+    }
 }
 
 /// Request payload for Ollama chat API.
@@ -50,20 +131,321 @@ struct ChatRequest {
     model: String,
     messages: Vec<Message>,
     stream: bool,
+    /// Only sent for models that advertise the "thinking" capability.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<bool>,
+    #[serde(skip_serializing_if = "ChatOptions::is_empty")]
+    options: ChatOptions,
+    /// Left out to keep Ollama's default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<KeepAlive>,
+}
+
+/// Request payload for Ollama's generate API: a bare prompt, completed
+/// without chat roles.
+#[derive(Debug, Clone, Serialize)]
+struct GenerateRequest {
+    model: String,
+    prompt: String,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<bool>,
+    #[serde(skip_serializing_if = "ChatOptions::is_empty")]
+    options: ChatOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<KeepAlive>,
+}
+
+/// How long Ollama keeps the model loaded after a request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum KeepAlive {
+    /// Seconds; negative keeps the model loaded until Ollama stops.
+    Seconds(i64),
+    /// A duration such as "10m" or "1h".
+    Duration(String),
+}
+
+impl KeepAlive {
+    /// Parse the configured value, or `None` for Ollama's default.
+    ///
+    /// Ollama only accepts bare numbers like "-1" or "0" as JSON numbers.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() || value == config::DEFAULT_KEEP_ALIVE {
+            return None;
+        }
+        Some(match value.parse() {
+            Ok(seconds) => Self::Seconds(seconds),
+            Err(_) => Self::Duration(value.to_string()),
+        })
+    }
+}
+
+/// Model parameters sent in the request's `options` field.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChatOptions {
+    /// Maximum number of tokens to generate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    /// Sampling temperature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling cutoff.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Context window size in tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// Generation halts when any of these strings is produced.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl ChatOptions {
+    /// Options for the configured generation settings, leaving out values
+    /// that match Ollama's defaults.
+    pub fn from_config(config: &config::Config) -> Self {
+        Self {
+            num_predict: config
+                .concise_mode
+                .then_some(config.concise_max_tokens)
+                .flatten(),
+            temperature: (config.temperature != config::DEFAULT_TEMPERATURE)
+                .then_some(config.temperature),
+            top_p: (config.top_p != config::DEFAULT_TOP_P).then_some(config.top_p),
+            num_ctx: config.context_window(),
+            stop: config
+                .stop_sequences
+                .iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Request payload for OpenAI-compatible chat completions.
+#[derive(Debug, Clone, Serialize)]
+struct OpenAiRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+impl OpenAiRequest {
+    fn new(model: String, messages: Vec<Message>, stream: bool, options: &ChatOptions) -> Self {
+        // These servers take images in a different shape, so they are only
+        // sent in Ollama's format
+        let messages = messages
+            .into_iter()
+            .map(|m| Message { images: None, ..m })
+            .collect();
+        Self {
+            model,
+            messages,
+            stream,
+            max_tokens: options.num_predict,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop: options.stop.clone(),
+        }
+    }
+}
+
+/// Response from an OpenAI-compatible chat API (non-streaming).
+#[derive(Debug, Clone, Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OpenAiChoice {
+    message: Message,
+}
+
+/// One `data:` payload of an OpenAI-compatible stream.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct OpenAiChunk {
+    #[serde(default)]
+    choices: Vec<OpenAiStreamChoice>,
+    /// Sent on the last chunk by servers that report usage.
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct OpenAiStreamChoice {
+    #[serde(default)]
+    delta: OpenAiDelta,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct OpenAiDelta {
+    #[serde(default)]
+    content: Option<String>,
+    /// Reasoning output from servers that separate it (e.g. llama.cpp).
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+/// A parsed line of a server-sent event stream.
+#[derive(Debug, Clone, PartialEq)]
+enum SseLine {
+    /// A JSON `data:` payload.
+    Data(OpenAiChunk),
+    /// The `data: [DONE]` terminator.
+    Done,
+    /// Blank lines, comments and fields other than `data`.
+    Ignored,
+}
+
+/// Parse one line of an OpenAI-compatible SSE stream.
+fn parse_sse_line(line: &str) -> Result<SseLine, String> {
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(SseLine::Ignored);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(SseLine::Done);
+    }
+    serde_json::from_str(data)
+        .map(SseLine::Data)
+        .map_err(|e| format!("Parse error: {}", e))
+}
+
+/// Response from the OpenAI-compatible model listing.
+#[derive(Debug, Clone, Deserialize)]
+struct OpenAiModels {
+    data: Vec<OpenAiModel>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OpenAiModel {
+    id: String,
 }
 
 /// Response from Ollama chat API (non-streaming).
-#[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize)]
 struct ChatResponse {
-    message: Message,
+    message: StreamMessage,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
 }
 
 /// Streaming response chunk from Ollama.
 #[derive(Debug, Clone, Deserialize)]
 struct StreamChunk {
     message: Option<StreamMessage>,
+    /// Text of a generate API chunk, which has no `message`.
+    #[serde(default)]
+    response: Option<String>,
+    /// Reasoning of a generate API chunk.
+    #[serde(default)]
+    thinking: Option<String>,
     done: bool,
+    /// Tokens in the prompt, reported on the final chunk.
+    #[serde(default)]
+    prompt_eval_count: u64,
+    /// Tokens generated, reported on the final chunk.
+    #[serde(default)]
+    eval_count: u64,
+    /// Nanoseconds spent generating, reported on the final chunk.
+    eval_duration: Option<u64>,
+}
+
+impl StreamChunk {
+    /// The chunk's text, from either the chat or the generate API.
+    fn into_message(self) -> Option<StreamMessage> {
+        self.message.or_else(|| {
+            self.response.map(|content| StreamMessage {
+                content,
+                thinking: self.thinking,
+            })
+        })
+    }
+
+    /// Generation speed from the final chunk, if the server reported it.
+    fn stats(&self) -> Option<StreamEvent> {
+        let duration = self.eval_duration.filter(|d| *d > 0)?;
+        if self.eval_count == 0 {
+            return None;
+        }
+        Some(StreamEvent::Stats {
+            tokens: self.eval_count as u32,
+            tokens_per_sec: self.eval_count as f32 / (duration as f32 / 1e9),
+        })
+    }
+}
+
+/// How fast a response was generated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GenerationStats {
+    /// Tokens generated.
+    pub tokens: u32,
+    pub tokens_per_sec: f32,
+}
+
+/// Tokens consumed by one or more requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Prompt tokens evaluated.
+    #[serde(default)]
+    pub prompt: u64,
+    /// Completion tokens generated.
+    #[serde(default)]
+    pub completion: u64,
+}
+
+impl TokenUsage {
+    /// Prompt and completion tokens combined.
+    pub fn total(&self) -> u64 {
+        self.prompt + self.completion
+    }
+
+    /// Whether no tokens were counted.
+    pub fn is_zero(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt += other.prompt;
+        self.completion += other.completion;
+    }
+}
+
+impl std::ops::SubAssign for TokenUsage {
+    /// Never goes below zero, for counts that predate what's subtracted.
+    fn sub_assign(&mut self, other: Self) {
+        self.prompt = self.prompt.saturating_sub(other.prompt);
+        self.completion = self.completion.saturating_sub(other.completion);
+    }
 }
 
 /// Response from Ollama tags API (model listing).
@@ -72,26 +454,244 @@ struct TagsResponse {
     models: Vec<ModelInfo>,
 }
 
+/// Response from Ollama version API.
+#[derive(Debug, Clone, Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// The connected Ollama server and the features its version supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Version string as reported, e.g. "0.9.2".
+    pub version: String,
+    /// Parsed (major, minor, patch); `None` for unparseable or dev builds.
+    parsed: Option<(u64, u64, u64)>,
+}
+
+impl ServerInfo {
+    pub fn new(version: impl Into<String>) -> Self {
+        let version = version.into();
+        let core = version
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default();
+        let mut numbers = core.split('.').map(|n| n.parse::<u64>().ok());
+        let parsed = match (numbers.next(), numbers.next(), numbers.next()) {
+            (Some(Some(major)), Some(Some(minor)), Some(Some(patch))) => {
+                Some((major, minor, patch))
+            }
+            _ => None,
+        }
+        // Source builds report 0.0.0
+        .filter(|v| *v != (0, 0, 0));
+
+        Self { version, parsed }
+    }
+
+    /// Whether the server is at least the given version. Unknown versions
+    /// are assumed to be recent.
+    pub fn at_least(&self, major: u64, minor: u64, patch: u64) -> bool {
+        self.parsed.is_none_or(|v| v >= (major, minor, patch))
+    }
+
+    /// Whether the server accepts the `think` request parameter.
+    pub fn supports_thinking(&self) -> bool {
+        self.at_least(0, 9, 0)
+    }
+}
+
+/// Request payload for Ollama show API.
+#[derive(Debug, Clone, Serialize)]
+struct ShowRequest {
+    model: String,
+}
+
+/// Response from Ollama show API (only the fields we use).
+#[derive(Debug, Clone, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    capabilities: Vec<String>,
+    /// Architecture-prefixed GGUF metadata, e.g. `llama.context_length`.
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    modelfile: String,
+    #[serde(default)]
+    template: String,
+    #[serde(default)]
+    system: String,
+    #[serde(default)]
+    parameters: String,
+    #[serde(default)]
+    license: String,
+}
+
+/// Details about a model reported by the show API.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModelShow {
+    /// Capabilities such as "completion", "thinking" or "vision".
+    pub capabilities: Vec<String>,
+    /// Maximum context window in tokens, if reported.
+    pub context_length: Option<u64>,
+    /// The Modelfile the model was created from.
+    pub modelfile: String,
+    /// Prompt template wrapped around each message.
+    pub template: String,
+    /// System prompt baked into the model.
+    pub system: String,
+    /// `PARAMETER` lines, one `name value` per line.
+    pub parameters: String,
+    pub license: String,
+}
+
+impl ModelShow {
+    /// Whether the model accepts the `think` request parameter.
+    pub fn supports_thinking(&self) -> bool {
+        self.capabilities.iter().any(|c| c == "thinking")
+    }
+
+    /// Whether the model can read attached images.
+    pub fn supports_vision(&self) -> bool {
+        self.capabilities.iter().any(|c| c == "vision")
+    }
+}
+
+impl From<ShowResponse> for ModelShow {
+    fn from(show: ShowResponse) -> Self {
+        let context_length = show
+            .model_info
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64());
+
+        Self {
+            capabilities: show.capabilities,
+            context_length,
+            modelfile: show.modelfile,
+            template: show.template,
+            system: show.system,
+            parameters: show.parameters,
+            license: show.license,
+        }
+    }
+}
+
 /// Information about a single model from the tags API.
 #[derive(Debug, Clone, Deserialize)]
 struct ModelInfo {
     name: String,
     size: u64,
+    /// Missing from older servers and some imported models.
+    #[serde(default)]
+    details: ModelDetails,
+}
+
+/// The `details` object of a model in the tags API.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct ModelDetails {
+    family: String,
+    parameter_size: String,
+    quantization_level: String,
 }
 
 /// A model available in Ollama with display information.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct AvailableModel {
     /// Model name/tag (e.g., "llama3.2:3b")
     pub name: String,
     /// Human-readable size (e.g., "2.0 GB")
     pub display_size: String,
+    /// Parameter count (e.g., "3.2B"); empty when unknown.
+    pub parameter_size: String,
+    /// Quantization (e.g., "Q4_K_M"); empty when unknown.
+    pub quantization: String,
+    /// Model family (e.g., "llama"); empty when unknown.
+    pub family: String,
 }
 
-/// Message content in a streaming chunk.
+impl AvailableModel {
+    /// Compact dropdown entry, e.g. "llama3.2:3b · 3.2B · Q4_K_M · 2.0 GB".
+    pub fn label(&self) -> String {
+        if self.parameter_size.is_empty() && self.quantization.is_empty() {
+            return if self.display_size.is_empty() {
+                self.name.clone()
+            } else {
+                format!("{} ({})", self.name, self.display_size)
+            };
+        }
+        [
+            &self.name,
+            &self.parameter_size,
+            &self.quantization,
+            &self.display_size,
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" · ")
+    }
+}
+
+/// Message content in a streaming chunk or a whole response.
 #[derive(Debug, Clone, Deserialize)]
 struct StreamMessage {
+    #[serde(default)]
     content: String,
+    /// Reasoning output, present when `think` is enabled.
+    #[serde(default)]
+    thinking: Option<String>,
+}
+
+/// Request payload for Ollama pull API.
+#[derive(Debug, Clone, Serialize)]
+struct PullRequest {
+    model: String,
+    stream: bool,
+}
+
+/// Request payload for Ollama delete API.
+#[derive(Debug, Clone, Serialize)]
+struct DeleteRequest {
+    model: String,
+}
+
+/// Progress line from the pull API.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PullChunk {
+    #[serde(default)]
+    status: String,
+    /// Bytes downloaded of the current layer.
+    #[serde(default)]
+    completed: u64,
+    /// Size of the current layer.
+    #[serde(default)]
+    total: u64,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Event sent while pulling a model.
+#[derive(Debug, Clone)]
+pub enum PullEvent {
+    /// Bytes downloaded so far of the layer being pulled.
+    Progress { completed: u64, total: u64 },
+    /// The model is installed.
+    Done,
+    /// The pull failed.
+    Error(String),
+}
+
+/// Explain a pull failure, calling out names the registry doesn't know.
+fn pull_error(name: &str, error: &str) -> String {
+    if error.contains("file does not exist") || error.contains("not found") {
+        format!("Model {} not found in the registry", name)
+    } else {
+        format!("Pull failed: {}", error)
+    }
 }
 
 /// Event sent during streaming response.
@@ -99,17 +699,52 @@ struct StreamMessage {
 pub enum StreamEvent {
     /// A chunk of content arrived.
     Chunk(String),
-    /// Stream completed successfully.
-    Done,
+    /// Generation speed, sent just before `Done` when the server reports it.
+    Stats { tokens: u32, tokens_per_sec: f32 },
+    /// Stream completed successfully, with the tokens it used.
+    Done(TokenUsage),
+    /// The requested model isn't installed (HTTP 404).
+    ModelNotFound(String),
     /// An error occurred.
     Error(String),
 }
 
+/// Cancellation signal shared by everything working on one turn.
+#[derive(Debug, Clone)]
+pub struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    /// Create a token and the sender that triggers it.
+    pub fn new() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (tx, Self(rx))
+    }
+
+    /// Resolve once cancellation is requested.
+    pub async fn cancelled(&mut self) {
+        // A dropped sender means the turn finished normally, not a cancel
+        if self.0.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
 /// Ollama client for making API requests.
 pub struct Client {
     url: String,
     model: String,
-    http: reqwest::Client,
+    format: ApiFormat,
+    think: Option<bool>,
+    options: ChatOptions,
+    timeout: Duration,
+    /// Bearer token for servers behind an authenticating proxy.
+    api_key: String,
+    /// Times a failed connection is retried.
+    max_retries: u32,
+    keep_alive: Option<KeepAlive>,
+    cancel: Option<CancelToken>,
+    /// Shared connection pool, or why it couldn't be set up.
+    http: Result<reqwest::Client, String>,
 }
 
 impl Default for Client {
@@ -118,203 +753,1384 @@ impl Default for Client {
     }
 }
 
-/// Format bytes into human-readable size.
-fn format_size(bytes: u64) -> String {
-    const GB: u64 = 1024 * 1024 * 1024;
-    const MB: u64 = 1024 * 1024;
-    if bytes >= GB {
-        format!("{:.1} GB", bytes as f64 / GB as f64)
+/// Derive another API endpoint URL from the configured chat URL.
+fn api_url(base_url: &str, endpoint: &str) -> String {
+    let target = format!("/api/{}", endpoint);
+    base_url
+        .replace("/api/chat", &target)
+        .replace("/api/generate", &target)
+}
+
+/// Derive another endpoint URL from an OpenAI-compatible chat URL, e.g.
+/// `/v1/chat/completions` to `/v1/models`.
+fn openai_url(base_url: &str, endpoint: &str) -> String {
+    base_url.replace("/chat/completions", &format!("/{}", endpoint))
+}
+
+/// How long to wait for Ollama to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Overall limit for quick metadata requests (tags, show, version), so a
+/// server that is down doesn't hold up the popup.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Headers sent with every request: `Authorization: Bearer <key>` when an
+/// API key is set, nothing otherwise.
+///
+/// The value is marked sensitive so it never shows up in debug output.
+fn auth_headers(api_key: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let api_key = api_key.trim();
+    if !api_key.is_empty()
+        && let Ok(mut value) = HeaderValue::from_str(&format!("Bearer {}", api_key))
+    {
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    headers
+}
+
+/// HTTP client for chat requests.
+///
+/// Uses a read timeout rather than a total one: a long answer may stream
+/// for minutes, but a gap of `timeout` between chunks (or before the first
+/// one, e.g. while a model loads) means Ollama is stuck.
+///
+/// A client that can't be built is an error rather than a default one,
+/// which would skip the proxy and the API key.
+fn chat_http_client(timeout: Duration, api_key: &str) -> Result<reqwest::Client, String> {
+    http::client_builder()
+        .default_headers(auth_headers(api_key))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(timeout)
+        .build()
+        .map_err(client_error)
+}
+
+/// HTTP client for metadata requests.
+fn metadata_http_client(api_key: &str) -> Result<reqwest::Client, String> {
+    http::client_builder()
+        .default_headers(auth_headers(api_key))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(METADATA_TIMEOUT)
+        .build()
+        .map_err(client_error)
+}
+
+fn client_error(error: reqwest::Error) -> String {
+    format!("Could not set up the connection: {}", error)
+}
+
+/// Describe a failed chat request, calling out timeouts explicitly.
+fn request_error(kind: &str, error: &reqwest::Error, timeout: Duration) -> String {
+    if error.is_timeout() {
+        format!("Request timed out after {}s", timeout.as_secs())
+    } else if is_refused(error) {
+        format!("{}: connection refused", kind)
     } else {
-        format!("{:.0} MB", bytes as f64 / MB as f64)
+        format!("{}: {}", kind, error)
     }
 }
 
-impl Client {
-    /// Create a new Ollama client with custom URL and model.
-    pub fn new(url: impl Into<String>, model: impl Into<String>) -> Self {
-        Self {
-            url: url.into(),
-            model: model.into(),
-            http: reqwest::Client::new(),
+/// Whether nothing is listening at the server's address, which reqwest
+/// only reveals deep in the error's source chain.
+fn is_refused(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused)
+        {
+            return true;
         }
+        source = err.source();
     }
+    false
+}
 
-    /// List available models from Ollama.
-    ///
-    /// Queries the /api/tags endpoint to get all installed models.
-    pub async fn list_models(base_url: &str) -> Result<Vec<AvailableModel>, String> {
-        // Convert chat URL to tags URL
-        let tags_url = base_url
-            .replace("/api/chat", "/api/tags")
-            .replace("/api/generate", "/api/tags");
+/// Error body of a failed request: Ollama sends `{"error": "..."}`,
+/// OpenAI-compatible servers `{"error": {"message": "..."}}`.
+#[derive(Debug, Clone, Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
 
-        let http = reqwest::Client::new();
-        let response = http
-            .get(&tags_url)
-            .send()
-            .await
-            .map_err(|e| format!("Connection error: {}", e))?;
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Text(String),
+    Object { message: String },
+}
 
-        if !response.status().is_success() {
-            return Err(format!("Ollama error: {}", response.status()));
-        }
+/// Why the server turned a chat request down.
+#[derive(Debug, Clone, PartialEq)]
+enum Rejection {
+    /// The model isn't installed.
+    ModelNotFound,
+    Error(String),
+}
 
-        let tags_response: TagsResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+/// Read why a chat request failed, preferring the server's own explanation
+/// over the bare status.
+///
+/// A 404 saying so is a missing model; one without an error body means the
+/// URL doesn't point at a chat API.
+async fn rejection(kind: &str, response: reqwest::Response) -> Rejection {
+    let status = response.status();
+    let detail = response
+        .json::<ErrorBody>()
+        .await
+        .ok()
+        .map(|body| match body.error {
+            ErrorDetail::Text(text) | ErrorDetail::Object { message: text } => text,
+        });
 
-        Ok(tags_response
-            .models
-            .into_iter()
-            .map(|m| AvailableModel {
-                name: m.name,
-                display_size: format_size(m.size),
-            })
-            .collect())
+    match detail {
+        Some(detail) if status == reqwest::StatusCode::NOT_FOUND && is_missing_model(&detail) => {
+            Rejection::ModelNotFound
+        }
+        Some(detail) => Rejection::Error(format!("{}: {} ({})", kind, detail, status)),
+        None if status == reqwest::StatusCode::NOT_FOUND => Rejection::Error(format!(
+            "{}: {} (check the server URL in the settings)",
+            kind, status
+        )),
+        None => Rejection::Error(format!("{}: {}", kind, status)),
     }
+}
 
-    /// Send a chat completion request to Ollama (non-streaming).
-    ///
-    /// # Arguments
-    /// * `system_prompt` - The system message providing context and instructions
-    /// * `messages` - The conversation history as (role, content) tuples
-    ///
-    /// # Returns
-    /// The assistant's response content, or an error message.
-    #[allow(dead_code)]
-    pub async fn chat(
-        &self,
-        system_prompt: String,
-        messages: Vec<(String, String)>,
-    ) -> Result<String, String> {
-        let mut ollama_messages = vec![Message::system(system_prompt)];
+/// Whether a server error says the requested model isn't there, as in
+/// Ollama's `model "llama9" not found, try pulling it first`.
+fn is_missing_model(detail: &str) -> bool {
+    let detail = detail.to_lowercase();
+    detail.starts_with("model") && detail.contains("not found")
+}
 
-        ollama_messages.extend(
-            messages
-                .into_iter()
-                .map(|(role, content)| Message { role, content }),
-        );
+/// Explain a chat request for a model that isn't installed.
+pub fn model_not_found(model: &str) -> String {
+    format!(
+        "Model '{}' is not installed. Pull it or pick another from the dropdown.",
+        model
+    )
+}
 
-        let request = ChatRequest {
-            model: self.model.clone(),
-            messages: ollama_messages,
-            stream: false,
-        };
+/// Delay before the first retry of a failed connection; doubled for each
+/// one after it.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
 
-        let response = self
-            .http
-            .post(&self.url)
-            .json(&request)
-            .send()
-            .await
+/// Send a request, retrying connection failures with exponential backoff.
+///
+/// Ollama briefly refuses connections while it loads a model, so those are
+/// worth another try. Timeouts and HTTP error statuses are returned as they
+/// are. A failure comes with the number of attempts made.
+async fn send_with_retry(
+    request: reqwest::RequestBuilder,
+    max_retries: u32,
+) -> Result<reqwest::Response, (reqwest::Error, u32)> {
+    let mut attempts = 1;
+    let mut delay = RETRY_DELAY;
+    loop {
+        // JSON bodies can always be cloned; anything else gets one try
+        let Some(attempt) = request.try_clone() else {
+            return request.send().await.map_err(|e| (e, attempts));
+        };
+        match attempt.send().await {
+            Err(e) if attempts <= max_retries && is_connection_error(&e) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempts += 1;
+            }
+            result => return result.map_err(|e| (e, attempts)),
+        }
+    }
+}
+
+/// Whether the server couldn't be connected to at all. Only then is it
+/// certain the request never arrived; a connection dropped after sending
+/// may already have started a generation.
+fn is_connection_error(error: &reqwest::Error) -> bool {
+    error.is_connect() && !error.is_timeout()
+}
+
+/// Describe a request that failed after `attempts` tries.
+fn retry_error(kind: &str, error: &reqwest::Error, attempts: u32, timeout: Duration) -> String {
+    let message = request_error(kind, error, timeout);
+    if attempts > 1 {
+        format!("{} (after {} attempts)", message, attempts)
+    } else {
+        message
+    }
+}
+
+/// Prepend the system prompt to the conversation; an empty prompt sends no
+/// system message at all.
+fn build_messages(system_prompt: String, messages: Vec<Message>) -> Vec<Message> {
+    let system = (!system_prompt.is_empty()).then(|| Message::system(system_prompt));
+    system.into_iter().chain(messages).collect()
+}
+
+/// Remove and return the first complete line from a byte buffer.
+///
+/// Network chunks can end mid-line (or mid-character), so incomplete data
+/// stays in the buffer until the rest arrives.
+fn take_line(buffer: &mut Vec<u8>) -> Option<String> {
+    let end = buffer.iter().position(|b| *b == b'\n')?;
+    let line: Vec<u8> = buffer.drain(..=end).collect();
+    Some(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+/// Forward an OpenAI-compatible SSE stream as stream events.
+///
+/// Events may be split across network chunks, so bytes are buffered until
+/// a full line has arrived.
+async fn stream_openai(
+    response: reqwest::Response,
+    tx: mpsc::Sender<StreamEvent>,
+    timeout: Duration,
+) {
+    let mut stream = response.bytes_stream();
+    let mut buffer = Vec::new();
+    let mut usage = TokenUsage::default();
+    let mut in_thinking = false;
+
+    while let Some(chunk_result) = stream.next().await {
+        let bytes = match chunk_result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = tx
+                    .send(StreamEvent::Error(request_error(
+                        "Stream error",
+                        &e,
+                        timeout,
+                    )))
+                    .await;
+                return;
+            }
+        };
+        buffer.extend_from_slice(&bytes);
+
+        while let Some(line) = take_line(&mut buffer) {
+            let chunk = match parse_sse_line(&line) {
+                Ok(SseLine::Data(chunk)) => chunk,
+                Ok(SseLine::Ignored) => continue,
+                Ok(SseLine::Done) => {
+                    if in_thinking {
+                        let _ = tx.send(StreamEvent::Chunk("</think>".to_string())).await;
+                    }
+                    let _ = tx.send(StreamEvent::Done(usage)).await;
+                    return;
+                }
+                Err(e) => {
+                    let _ = tx.send(StreamEvent::Error(e)).await;
+                    return;
+                }
+            };
+
+            if let Some(reported) = chunk.usage {
+                usage = TokenUsage {
+                    prompt: reported.prompt_tokens,
+                    completion: reported.completion_tokens,
+                };
+            }
+
+            let mut text = String::new();
+            for choice in chunk.choices {
+                if let Some(thinking) = choice.delta.reasoning_content
+                    && !thinking.is_empty()
+                {
+                    if !in_thinking {
+                        text.push_str("<think>");
+                        in_thinking = true;
+                    }
+                    text.push_str(&thinking);
+                }
+                if let Some(content) = choice.delta.content
+                    && !content.is_empty()
+                {
+                    if in_thinking {
+                        text.push_str("</think>");
+                        in_thinking = false;
+                    }
+                    text.push_str(&content);
+                }
+            }
+            if !text.is_empty() && tx.send(StreamEvent::Chunk(text)).await.is_err() {
+                return; // Receiver dropped
+            }
+        }
+    }
+
+    // Stream ended without [DONE]
+    let _ = tx.send(StreamEvent::Done(usage)).await;
+}
+
+/// Forward an Ollama stream of newline-delimited JSON as stream events.
+///
+/// Chat and generate chunks are both understood. An object may be split
+/// across network chunks, so bytes are buffered
+/// until a full line has arrived. Reasoning is forwarded wrapped in
+/// `<think>` tags so it can be told apart from the answer downstream.
+async fn stream_ollama<B: AsRef<[u8]>>(
+    mut stream: impl Stream<Item = Result<B, reqwest::Error>> + Unpin,
+    tx: mpsc::Sender<StreamEvent>,
+    timeout: Duration,
+) {
+    let mut buffer = Vec::new();
+    let mut in_thinking = false;
+
+    while let Some(chunk_result) = stream.next().await {
+        let bytes = match chunk_result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = tx
+                    .send(StreamEvent::Error(request_error(
+                        "Stream error",
+                        &e,
+                        timeout,
+                    )))
+                    .await;
+                return;
+            }
+        };
+        buffer.extend_from_slice(bytes.as_ref());
+
+        while let Some(line) = take_line(&mut buffer) {
+            if line.is_empty() {
+                continue;
+            }
+            let chunk = match serde_json::from_str::<StreamChunk>(&line) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = tx
+                        .send(StreamEvent::Error(format!("Parse error: {}", e)))
+                        .await;
+                    return;
+                }
+            };
+
+            if chunk.done {
+                if in_thinking {
+                    let _ = tx.send(StreamEvent::Chunk("</think>".to_string())).await;
+                }
+                if let Some(stats) = chunk.stats() {
+                    let _ = tx.send(stats).await;
+                }
+                let usage = TokenUsage {
+                    prompt: chunk.prompt_eval_count,
+                    completion: chunk.eval_count,
+                };
+                let _ = tx.send(StreamEvent::Done(usage)).await;
+                return;
+            }
+            let Some(msg) = chunk.into_message() else {
+                continue;
+            };
+
+            let mut text = String::new();
+            if let Some(thinking) = msg.thinking
+                && !thinking.is_empty()
+            {
+                if !in_thinking {
+                    text.push_str("<think>");
+                    in_thinking = true;
+                }
+                text.push_str(&thinking);
+            }
+            if !msg.content.is_empty() {
+                if in_thinking {
+                    text.push_str("</think>");
+                    in_thinking = false;
+                }
+                text.push_str(&msg.content);
+            }
+            if !text.is_empty() && tx.send(StreamEvent::Chunk(text)).await.is_err() {
+                return; // Receiver dropped
+            }
+        }
+    }
+
+    // Stream ended without done flag (and without stats)
+    let _ = tx.send(StreamEvent::Done(TokenUsage::default())).await;
+}
+
+/// Format bytes into human-readable size.
+fn format_size(bytes: u64) -> String {
+    const GB: u64 = 1024 * 1024 * 1024;
+    const MB: u64 = 1024 * 1024;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else {
+        format!("{:.0} MB", bytes as f64 / MB as f64)
+    }
+}
+
+impl Client {
+    /// Create a new Ollama client with custom URL and model.
+    pub fn new(url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            model: model.into(),
+            format: ApiFormat::default(),
+            think: None,
+            options: ChatOptions::default(),
+            timeout: Duration::from_secs(config::DEFAULT_TIMEOUT_SECS),
+            api_key: String::new(),
+            max_retries: config::DEFAULT_MAX_RETRIES,
+            keep_alive: None,
+            cancel: None,
+            http: chat_http_client(Duration::from_secs(config::DEFAULT_TIMEOUT_SECS), ""),
+        }
+    }
+
+    /// Speak the given wire format instead of the native Ollama API.
+    pub fn with_format(mut self, format: ApiFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the `think` request parameter (omitted when `None`).
+    pub fn with_think(mut self, think: Option<bool>) -> Self {
+        self.think = think;
+        self
+    }
+
+    /// Give up when Ollama sends nothing for this long.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self.http = chat_http_client(timeout, &self.api_key);
+        self
+    }
+
+    /// Authenticate with a bearer token; an empty key sends no header.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = api_key.into();
+        self.http = chat_http_client(self.timeout, &self.api_key);
+        self
+    }
+
+    /// Retry a refused or dropped connection up to this many times.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set how long Ollama keeps the model loaded (omitted when `None`).
+    pub fn with_keep_alive(mut self, keep_alive: Option<KeepAlive>) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Stop streaming responses when the token is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Set the model parameters sent with each request.
+    pub fn with_options(mut self, options: ChatOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Probe the server version.
+    ///
+    /// Queries the /api/version endpoint.
+    pub async fn server_info(base_url: &str, api_key: &str) -> Result<ServerInfo, String> {
+        let version_url = api_url(base_url, "version");
+
+        let http = metadata_http_client(api_key)?;
+        let response = http
+            .get(&version_url)
+            .send()
+            .await
             .map_err(|e| format!("Connection error: {}", e))?;
 
         if !response.status().is_success() {
             return Err(format!("Ollama error: {}", response.status()));
         }
 
-        let chat_response: ChatResponse = response
+        let version_response: VersionResponse = response
             .json()
             .await
             .map_err(|e| format!("Parse error: {}", e))?;
 
-        Ok(chat_response.message.content)
+        Ok(ServerInfo::new(version_response.version))
     }
 
-    /// Send a streaming chat request to Ollama.
+    /// Check that the server is reachable.
     ///
-    /// Returns a receiver that yields content chunks as they arrive.
-    pub async fn chat_stream(
-        &self,
-        system_prompt: String,
-        messages: Vec<(String, String)>,
-    ) -> mpsc::Receiver<StreamEvent> {
-        let (tx, rx) = mpsc::channel(32);
+    /// A cheap GET of /api/version (or /v1/models for OpenAI-compatible
+    /// servers) that never loads a model.
+    pub async fn ping(base_url: &str, api_key: &str, format: ApiFormat) -> Result<(), String> {
+        let ping_url = match format {
+            ApiFormat::Ollama => api_url(base_url, "version"),
+            ApiFormat::OpenAI => openai_url(base_url, "models"),
+        };
 
-        let mut ollama_messages = vec![Message::system(system_prompt)];
-        ollama_messages.extend(
-            messages
-                .into_iter()
-                .map(|(role, content)| Message { role, content }),
-        );
+        let http = metadata_http_client(api_key)?;
+        let response = http
+            .get(&ping_url)
+            .send()
+            .await
+            .map_err(|e| request_error("Connection error", &e, METADATA_TIMEOUT))?;
 
-        let request = ChatRequest {
-            model: self.model.clone(),
-            messages: ollama_messages,
-            stream: true,
+        if !response.status().is_success() {
+            return Err(format!("Server error: {}", response.status()));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch details about a model from Ollama.
+    ///
+    /// Queries the /api/show endpoint. Older Ollama versions omit
+    /// capabilities, which are then reported as empty.
+    pub async fn show_model(
+        base_url: &str,
+        api_key: &str,
+        name: &str,
+    ) -> Result<ModelShow, String> {
+        let show_url = api_url(base_url, "show");
+        let request = ShowRequest {
+            model: name.to_string(),
         };
 
-        let http = self.http.clone();
-        let url = self.url.clone();
+        let http = metadata_http_client(api_key)?;
+        let response = http
+            .post(&show_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Connection error: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Ollama error: {}", response.status()));
+        }
+
+        let show_response: ShowResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {}", e))?;
+
+        Ok(show_response.into())
+    }
+
+    /// List available models from Ollama.
+    ///
+    /// Queries the /api/tags endpoint to get all installed models, or
+    /// /v1/models for OpenAI-compatible servers.
+    pub async fn list_models(
+        base_url: &str,
+        api_key: &str,
+        format: ApiFormat,
+        max_retries: u32,
+    ) -> Result<Vec<AvailableModel>, String> {
+        if format == ApiFormat::OpenAI {
+            return Self::list_openai_models(base_url, api_key, max_retries).await;
+        }
+
+        // Convert chat URL to tags URL
+        let tags_url = api_url(base_url, "tags");
+
+        let http = metadata_http_client(api_key)?;
+        let response = send_with_retry(http.get(&tags_url), max_retries)
+            .await
+            .map_err(|(e, attempts)| {
+                retry_error("Connection error", &e, attempts, METADATA_TIMEOUT)
+            })?;
+
+        if !response.status().is_success() {
+            return Err(format!("Ollama error: {}", response.status()));
+        }
+
+        let tags_response: TagsResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {}", e))?;
+
+        Ok(tags_response
+            .models
+            .into_iter()
+            .map(|m| AvailableModel {
+                name: m.name,
+                display_size: format_size(m.size),
+                parameter_size: m.details.parameter_size,
+                quantization: m.details.quantization_level,
+                family: m.details.family,
+            })
+            .collect())
+    }
+
+    /// List models from an OpenAI-compatible server, which reports no sizes.
+    async fn list_openai_models(
+        base_url: &str,
+        api_key: &str,
+        max_retries: u32,
+    ) -> Result<Vec<AvailableModel>, String> {
+        let models_url = openai_url(base_url, "models");
+
+        let http = metadata_http_client(api_key)?;
+        let response = send_with_retry(http.get(&models_url), max_retries)
+            .await
+            .map_err(|(e, attempts)| {
+                retry_error("Connection error", &e, attempts, METADATA_TIMEOUT)
+            })?;
+
+        if !response.status().is_success() {
+            return Err(format!("Server error: {}", response.status()));
+        }
+
+        let models: OpenAiModels = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {}", e))?;
+
+        Ok(models
+            .data
+            .into_iter()
+            .map(|m| AvailableModel {
+                name: m.id,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    /// Download a model from the Ollama registry.
+    ///
+    /// POSTs to the /api/pull endpoint and returns a receiver that yields
+    /// download progress until the model is installed or the pull fails.
+    pub async fn pull_model(
+        base_url: &str,
+        api_key: &str,
+        name: &str,
+    ) -> mpsc::Receiver<PullEvent> {
+        let (tx, rx) = mpsc::channel(32);
+
+        let pull_url = api_url(base_url, "pull");
+        let request = PullRequest {
+            model: name.to_string(),
+            stream: true,
+        };
+        let name = name.to_string();
+        // Downloads can take a long time, but a stalled one shouldn't hang
+        let http = chat_http_client(Duration::from_secs(config::DEFAULT_TIMEOUT_SECS), api_key);
 
         tokio::spawn(async move {
-            let response = match http.post(&url).json(&request).send().await {
+            let http = match http {
+                Ok(http) => http,
+                Err(err) => {
+                    let _ = tx.send(PullEvent::Error(err)).await;
+                    return;
+                }
+            };
+            let response = match http.post(&pull_url).json(&request).send().await {
                 Ok(r) => r,
                 Err(e) => {
                     let _ = tx
-                        .send(StreamEvent::Error(format!("Connection error: {}", e)))
+                        .send(PullEvent::Error(format!("Connection error: {}", e)))
                         .await;
                     return;
                 }
             };
 
             if !response.status().is_success() {
-                let _ = tx
-                    .send(StreamEvent::Error(format!(
-                        "Ollama error: {}",
-                        response.status()
-                    )))
-                    .await;
+                let status = response.status();
+                let error = response
+                    .json::<PullChunk>()
+                    .await
+                    .ok()
+                    .and_then(|chunk| chunk.error)
+                    .unwrap_or_else(|| status.to_string());
+                let _ = tx.send(PullEvent::Error(pull_error(&name, &error))).await;
                 return;
             }
 
             let mut stream = response.bytes_stream();
+            let mut buffer = Vec::new();
 
             while let Some(chunk_result) = stream.next().await {
                 match chunk_result {
-                    Ok(bytes) => {
-                        // Ollama returns newline-delimited JSON
-                        let text = String::from_utf8_lossy(&bytes);
-                        for line in text.lines() {
-                            if line.is_empty() {
-                                continue;
-                            }
-                            match serde_json::from_str::<StreamChunk>(line) {
-                                Ok(chunk) => {
-                                    if chunk.done {
-                                        let _ = tx.send(StreamEvent::Done).await;
-                                        return;
-                                    }
-                                    if let Some(msg) = chunk.message
-                                        && !msg.content.is_empty()
-                                        && tx.send(StreamEvent::Chunk(msg.content)).await.is_err()
-                                    {
-                                        return; // Receiver dropped
-                                    }
-                                }
-                                Err(e) => {
-                                    let _ = tx
-                                        .send(StreamEvent::Error(format!("Parse error: {}", e)))
-                                        .await;
-                                    return;
-                                }
-                            }
-                        }
-                    }
+                    Ok(bytes) => buffer.extend_from_slice(&bytes),
                     Err(e) => {
                         let _ = tx
-                            .send(StreamEvent::Error(format!("Stream error: {}", e)))
+                            .send(PullEvent::Error(format!("Pull failed: {}", e)))
                             .await;
                         return;
                     }
                 }
+
+                while let Some(line) = take_line(&mut buffer) {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let chunk = match serde_json::from_str::<PullChunk>(&line) {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            let _ = tx
+                                .send(PullEvent::Error(format!("Parse error: {}", e)))
+                                .await;
+                            return;
+                        }
+                    };
+
+                    let event = if let Some(error) = chunk.error {
+                        PullEvent::Error(pull_error(&name, &error))
+                    } else if chunk.status == "success" {
+                        PullEvent::Done
+                    } else {
+                        PullEvent::Progress {
+                            completed: chunk.completed,
+                            total: chunk.total,
+                        }
+                    };
+                    let finished = !matches!(event, PullEvent::Progress { .. });
+                    if tx.send(event).await.is_err() || finished {
+                        return;
+                    }
+                }
             }
 
-            // Stream ended without done flag
-            let _ = tx.send(StreamEvent::Done).await;
+            let _ = tx
+                .send(PullEvent::Error(
+                    "Pull failed: connection closed early".to_string(),
+                ))
+                .await;
         });
 
         rx
     }
+
+    /// Remove an installed model.
+    ///
+    /// Sends a DELETE to the /api/delete endpoint.
+    pub async fn delete_model(base_url: &str, api_key: &str, name: &str) -> Result<(), String> {
+        let delete_url = api_url(base_url, "delete");
+        let request = DeleteRequest {
+            model: name.to_string(),
+        };
+
+        let http = metadata_http_client(api_key)?;
+        let response = http
+            .delete(&delete_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Connection error: {}", e))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("Model {} is not installed", name));
+        }
+        if !response.status().is_success() {
+            return Err(format!("Ollama error: {}", response.status()));
+        }
+
+        Ok(())
+    }
+
+    /// Send a chat completion request to Ollama (non-streaming).
+    ///
+    /// # Arguments
+    /// * `system_prompt` - The system message providing context and instructions
+    /// * `messages` - The conversation history
+    ///
+    /// # Returns
+    /// The assistant's response content and the tokens it used, or an
+    /// error message. Reasoning is wrapped in `<think>` tags, as when
+    /// streaming.
+    pub async fn chat(
+        &self,
+        system_prompt: String,
+        messages: Vec<Message>,
+    ) -> Result<(String, TokenUsage), String> {
+        let ollama_messages = build_messages(system_prompt, messages);
+
+        if self.format == ApiFormat::OpenAI {
+            let request =
+                OpenAiRequest::new(self.model.clone(), ollama_messages, false, &self.options);
+            let response = self.send(&request).await?;
+
+            if !response.status().is_success() {
+                return Err(self
+                    .rejection_error(self.format.error_label(), response)
+                    .await);
+            }
+
+            let chat_response: OpenAiResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Parse error: {}", e))?;
+
+            let usage = chat_response
+                .usage
+                .map(|reported| TokenUsage {
+                    prompt: reported.prompt_tokens,
+                    completion: reported.completion_tokens,
+                })
+                .unwrap_or_default();
+            return chat_response
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| (choice.message.content, usage))
+                .ok_or_else(|| "Parse error: response has no choices".to_string());
+        }
+
+        let request = self.chat_request(ollama_messages, false);
+        let response = self.send(&request).await?;
+
+        if !response.status().is_success() {
+            return Err(self
+                .rejection_error(self.format.error_label(), response)
+                .await);
+        }
+
+        let chat_response: ChatResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {}", e))?;
+
+        let usage = TokenUsage {
+            prompt: chat_response.prompt_eval_count,
+            completion: chat_response.eval_count,
+        };
+        let message = chat_response.message;
+        let content = match message.thinking.filter(|t| !t.is_empty()) {
+            Some(thinking) => format!("<think>{}</think>{}", thinking, message.content),
+            None => message.content,
+        };
+        Ok((content, usage))
+    }
+
+    /// Payload for Ollama's chat API with this client's settings.
+    fn chat_request(&self, messages: Vec<Message>, stream: bool) -> ChatRequest {
+        ChatRequest {
+            model: self.model.clone(),
+            messages,
+            stream,
+            think: self.think,
+            options: self.options.clone(),
+            keep_alive: self.keep_alive.clone(),
+        }
+    }
+
+    /// Describe a rejected non-streaming chat request.
+    async fn rejection_error(&self, kind: &str, response: reqwest::Response) -> String {
+        match rejection(kind, response).await {
+            Rejection::ModelNotFound => model_not_found(&self.model),
+            Rejection::Error(err) => err,
+        }
+    }
+
+    /// POST a non-streaming chat request, retrying failed connections.
+    async fn send(&self, request: &impl Serialize) -> Result<reqwest::Response, String> {
+        let http = self.http.as_ref().map_err(Clone::clone)?;
+        send_with_retry(http.post(&self.url).json(request), self.max_retries)
+            .await
+            .map_err(|(e, attempts)| retry_error("Connection error", &e, attempts, self.timeout))
+    }
+
+    /// Send a streaming chat request to Ollama.
+    ///
+    /// Returns a receiver that yields content chunks as they arrive, and a
+    /// handle that tears down the request when aborted. If a cancel token
+    /// is set the stream also stops, and the receiver closes, once it fires.
+    pub async fn chat_stream(
+        &self,
+        system_prompt: String,
+        messages: Vec<Message>,
+    ) -> (mpsc::Receiver<StreamEvent>, AbortHandle) {
+        let ollama_messages = build_messages(system_prompt, messages);
+
+        let body = match self.format {
+            ApiFormat::Ollama => serde_json::to_value(self.chat_request(ollama_messages, true)),
+            ApiFormat::OpenAI => serde_json::to_value(OpenAiRequest::new(
+                self.model.clone(),
+                ollama_messages,
+                true,
+                &self.options,
+            )),
+        };
+
+        self.spawn_stream(self.url.clone(), body, self.format)
+    }
+
+    /// Send a bare prompt to Ollama's generate API and stream the
+    /// completion, for models that continue text rather than chat.
+    pub async fn generate_stream(
+        &self,
+        prompt: String,
+    ) -> (mpsc::Receiver<StreamEvent>, AbortHandle) {
+        let body = serde_json::to_value(GenerateRequest {
+            model: self.model.clone(),
+            prompt,
+            stream: true,
+            think: self.think,
+            options: self.options.clone(),
+            keep_alive: self.keep_alive.clone(),
+        });
+
+        self.spawn_stream(api_url(&self.url, "generate"), body, ApiFormat::Ollama)
+    }
+
+    /// POST a streaming request and forward its events from a background
+    /// task. A body that couldn't be encoded, or a client that couldn't be
+    /// set up, is reported as the stream's error without sending anything.
+    fn spawn_stream(
+        &self,
+        url: String,
+        body: serde_json::Result<serde_json::Value>,
+        format: ApiFormat,
+    ) -> (mpsc::Receiver<StreamEvent>, AbortHandle) {
+        let (tx, rx) = mpsc::channel(32);
+        let http = self.http.clone();
+        let model = self.model.clone();
+        let timeout = self.timeout;
+        let max_retries = self.max_retries;
+        let cancel = self.cancel.clone();
+
+        let stream = async move {
+            let body = body.map_err(|e| format!("Could not encode the request: {}", e));
+            let (http, body) = match http.and_then(|http| body.map(|body| (http, body))) {
+                Ok(ready) => ready,
+                Err(err) => {
+                    let _ = tx.send(StreamEvent::Error(err)).await;
+                    return;
+                }
+            };
+            let response = match send_with_retry(http.post(&url).json(&body), max_retries).await {
+                Ok(r) => r,
+                Err((e, attempts)) => {
+                    let _ = tx
+                        .send(StreamEvent::Error(retry_error(
+                            "Connection error",
+                            &e,
+                            attempts,
+                            timeout,
+                        )))
+                        .await;
+                    return;
+                }
+            };
+
+            if !response.status().is_success() {
+                let event = match rejection(format.error_label(), response).await {
+                    Rejection::ModelNotFound => StreamEvent::ModelNotFound(model),
+                    Rejection::Error(err) => StreamEvent::Error(err),
+                };
+                let _ = tx.send(event).await;
+                return;
+            }
+
+            if format == ApiFormat::OpenAI {
+                stream_openai(response, tx, timeout).await;
+                return;
+            }
+
+            stream_ollama(response.bytes_stream(), tx, timeout).await;
+        };
+
+        // Dropping the stream on cancel closes the connection
+        let task = tokio::spawn(async move {
+            match cancel {
+                Some(mut cancel) => tokio::select! {
+                    () = stream => {}
+                    () = cancel.cancelled() => {}
+                },
+                None => stream.await,
+            }
+        });
+
+        (rx, task.abort_handle())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const TAGS: &str = r#"{"models":[{"name":"llava:7b","size":4700000000,
+        "details":{"family":"llama","parameter_size":"7B","quantization_level":"Q4_0"}}]}"#;
+
+    /// The streaming chat request a client with `config`'s options sends.
+    fn options_request(config: &config::Config) -> serde_json::Value {
+        let client = Client::new(config::DEFAULT_OLLAMA_URL, "llama3.2")
+            .with_options(ChatOptions::from_config(config));
+        serde_json::to_value(client.chat_request(Vec::new(), true)).unwrap()
+    }
+
+    /// Serve one response after dropping the first `drops` connections.
+    /// Returns the chat URL.
+    async fn mock_server(drops: usize, status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/chat", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for _ in 0..drops {
+                let (socket, _) = listener.accept().await.unwrap();
+                drop(socket);
+            }
+            respond(&listener, status, body).await;
+        });
+        url
+    }
+
+    /// Answer the next connection on `listener`, returning the start of
+    /// the request it sent.
+    async fn respond(listener: &TcpListener, status: &str, body: &str) -> String {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = [0; 4096];
+        let read = socket.read(&mut request).await.unwrap_or(0);
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let _ = socket.write_all(response.as_bytes()).await;
+        String::from_utf8_lossy(&request[..read]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_retries_refused_connections() {
+        // Refuse connections for a moment, the way Ollama does while it
+        // starts, then serve on the same port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = TcpListener::bind(address).await.unwrap();
+            respond(&listener, "200 OK", TAGS).await;
+        });
+        let url = format!("http://{}/api/chat", address);
+        let models = Client::list_models(&url, "", ApiFormat::Ollama, 3)
+            .await
+            .unwrap();
+        assert_eq!(models[0].label(), "llava:7b · 7B · Q4_0 · 4.4 GB");
+        assert_eq!(models[0].family, "llama");
+
+        // Out of retries: the error says how often it tried
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/chat", listener.local_addr().unwrap());
+        drop(listener);
+        let err = Client::list_models(&url, "", ApiFormat::Ollama, 1)
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Connection error"), "{}", err);
+        assert!(err.ends_with("(after 2 attempts)"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_not_resent() {
+        // The request reached the server, so sending it again could start
+        // a second generation
+        let url = mock_server(1, "200 OK", TAGS).await;
+        let err = Client::list_models(&url, "", ApiFormat::Ollama, 2)
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Connection error"), "{}", err);
+        assert!(!err.contains("attempts"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_ping_describes_failures() {
+        // Nothing listening on a port that was just freed
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/chat", listener.local_addr().unwrap());
+        drop(listener);
+        let err = Client::ping(&url, "", ApiFormat::Ollama).await.unwrap_err();
+        assert_eq!(err, "Connection error: connection refused");
+
+        let url = mock_server(0, "500 Internal Server Error", "").await;
+        let err = Client::ping(&url, "", ApiFormat::Ollama).await.unwrap_err();
+        assert_eq!(err, "Server error: 500 Internal Server Error");
+    }
+
+    #[tokio::test]
+    async fn test_chat_reports_usage() {
+        let body = r#"{"message":{"role":"assistant","content":"Hello"},"done":true,
+            "prompt_eval_count":12,"eval_count":3}"#;
+        let url = mock_server(0, "200 OK", body).await;
+        let (content, usage) = Client::new(url, "llama3.2")
+            .chat(String::new(), vec![Message::new("user", "Hi")])
+            .await
+            .unwrap();
+        assert_eq!(content, "Hello");
+        assert_eq!(usage.total(), 15);
+    }
+
+    #[tokio::test]
+    async fn test_chat_explains_rejections() {
+        let chat = |url: String| async move {
+            Client::new(url, "llama9")
+                .chat(String::new(), vec![Message::new("user", "Hi")])
+                .await
+                .unwrap_err()
+        };
+
+        let body = r#"{"error":"model \"llama9\" not found, try pulling it first"}"#;
+        let url = mock_server(0, "404 Not Found", body).await;
+        assert_eq!(chat(url).await, model_not_found("llama9"));
+
+        // Other 404s, e.g. from a proxy in front of the server, aren't
+        // about the model
+        let url = mock_server(0, "404 Not Found", r#"{"error":"route not found"}"#).await;
+        assert_eq!(
+            chat(url).await,
+            "Ollama error: route not found (404 Not Found)"
+        );
+
+        // A 404 without Ollama's error body is the wrong URL
+        let url = mock_server(0, "404 Not Found", "").await;
+        assert_eq!(
+            chat(url).await,
+            "Ollama error: 404 Not Found (check the server URL in the settings)"
+        );
+
+        let body = r#"{"error":"model requires more system memory"}"#;
+        let url = mock_server(0, "500 Internal Server Error", body).await;
+        assert_eq!(
+            chat(url).await,
+            "Ollama error: model requires more system memory (500 Internal Server Error)"
+        );
+    }
+
+    #[test]
+    fn test_parse_sse_line() {
+        let line = r#"data: {"choices":[{"index":0,"delta":{"content":"Hi"}}]}"#;
+        let Ok(SseLine::Data(chunk)) = parse_sse_line(line) else {
+            panic!("expected a data line");
+        };
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("Hi"));
+
+        assert_eq!(parse_sse_line("data: [DONE]"), Ok(SseLine::Done));
+        assert_eq!(parse_sse_line(""), Ok(SseLine::Ignored));
+        assert_eq!(parse_sse_line(": keep-alive"), Ok(SseLine::Ignored));
+        assert!(parse_sse_line("data: {not json").is_err());
+    }
+
+    #[test]
+    fn test_fit_to_budget_drops_oldest_exchanges() {
+        let mut messages = Vec::new();
+        for i in 0..50 {
+            messages.push(Message::new("user", format!("question {:02}", i)));
+            messages.push(Message::new("assistant", "a".repeat(100)));
+        }
+        messages.push(Message::new("user", "latest question"));
+
+        let system_prompt = "s".repeat(500);
+        let fitted = fit_to_budget(&system_prompt, messages.clone(), 1_000);
+
+        // The newest messages survive, starting on a question
+        assert_eq!(fitted.last(), messages.last());
+        assert_eq!(fitted[0].content, "question 46");
+        let size: usize = fitted.iter().map(|m| m.content.len()).sum();
+        assert!(system_prompt.len() + size <= 1_000);
+
+        // Everything fits: nothing is dropped
+        assert_eq!(fit_to_budget("", messages.clone(), 100_000), messages);
+
+        // The latest question is kept even when it alone is too big
+        let fitted = fit_to_budget(&system_prompt, messages.clone(), 10);
+        assert_eq!(fitted, vec![Message::new("user", "latest question")]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_ollama_joins_split_lines() {
+        let line = "{\"message\":{\"content\":\"Hello\"},\"done\":false}\n";
+        // Split mid-object, and the done line arrives in two pieces too
+        let (first, second) = line.as_bytes().split_at(17);
+        let chunks: Vec<Result<Vec<u8>, reqwest::Error>> = vec![
+            Ok(first.to_vec()),
+            Ok([second, b"{\"done\":"].concat()),
+            Ok(b"true}\n".to_vec()),
+        ];
+        let (tx, mut rx) = mpsc::channel(8);
+
+        stream_ollama(
+            futures_util::stream::iter(chunks),
+            tx,
+            Duration::from_secs(1),
+        )
+        .await;
+
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Chunk(text)) if text == "Hello"));
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Done(_))));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream() {
+        let body = "{\"response\":\"fn \",\"done\":false}\n\
+                    {\"response\":\"main()\",\"done\":false}\n\
+                    {\"response\":\"\",\"done\":true,\"eval_count\":2}\n";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/chat", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { respond(&listener, "200 OK", body).await });
+        let (mut rx, _) = Client::new(url, "codellama")
+            .generate_stream("".into())
+            .await;
+
+        let mut text = String::new();
+        while let Some(event) = rx.recv().await {
+            match event {
+                StreamEvent::Chunk(chunk) => text.push_str(&chunk),
+                StreamEvent::Done(usage) => {
+                    assert_eq!(usage.completion, 2);
+                    break;
+                }
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+        assert_eq!(text, "fn main()");
+
+        // Sent next to the configured chat endpoint
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/generate "), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_closes_stream() {
+        // A server that accepts the request but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/chat", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let (cancel_tx, cancel) = CancelToken::new();
+        let (mut rx, _) = Client::new(url, "llama3.2")
+            .with_cancel(cancel)
+            .chat_stream(String::new(), vec![Message::new("user", "Hi")])
+            .await;
+        cancel_tx.send(true).unwrap();
+
+        let closed = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await;
+        assert!(matches!(closed, Ok(None)));
+    }
+
+    #[test]
+    fn test_take_line_waits_for_newline() {
+        let mut buffer = b"{\"status\":\"pull".to_vec();
+        assert_eq!(take_line(&mut buffer), None);
+
+        buffer.extend_from_slice(b"ing\"}\r\n{\"sta");
+        assert_eq!(
+            take_line(&mut buffer).as_deref(),
+            Some("{\"status\":\"pulling\"}")
+        );
+        assert_eq!(buffer, b"{\"sta");
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        assert_eq!(KeepAlive::parse(""), None);
+        assert_eq!(KeepAlive::parse(config::DEFAULT_KEEP_ALIVE), None);
+        assert_eq!(KeepAlive::parse("-1"), Some(KeepAlive::Seconds(-1)));
+        assert_eq!(
+            KeepAlive::parse(" 1h "),
+            Some(KeepAlive::Duration("1h".to_string()))
+        );
+
+        let client = Client::new(config::DEFAULT_OLLAMA_URL, "llama3.2");
+        let request = serde_json::to_value(client.chat_request(Vec::new(), true)).unwrap();
+        assert!(request.get("keep_alive").is_none());
+
+        let client = client.with_keep_alive(KeepAlive::parse("-1"));
+        for stream in [false, true] {
+            let request = serde_json::to_value(client.chat_request(Vec::new(), stream)).unwrap();
+            assert_eq!(request["keep_alive"], -1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequences() {
+        let mut config = config::Config::default();
+        assert!(options_request(&config).get("options").is_none());
+
+        config.stop_sequences = vec!["```".to_string(), "\n\n".to_string()];
+        assert_eq!(
+            options_request(&config)["options"]["stop"],
+            serde_json::json!(["```", "\n\n"])
+        );
+
+        // A stopped generation ends like any other
+        let chunks: Vec<Result<&[u8], reqwest::Error>> = vec![
+            Ok(b"{\"message\":{\"content\":\"{}\"},\"done\":false}\n"),
+            Ok(b"{\"message\":{\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\"}\n"),
+        ];
+        let (tx, mut rx) = mpsc::channel(8);
+        stream_ollama(
+            futures_util::stream::iter(chunks),
+            tx,
+            Duration::from_secs(1),
+        )
+        .await;
+
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Chunk(text)) if text == "{}"));
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Done(_))));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_num_ctx_option() {
+        let mut config = config::Config::default();
+        assert!(options_request(&config).get("options").is_none());
+
+        config.num_ctx = Some(8192);
+        assert_eq!(options_request(&config)["options"]["num_ctx"], 8192);
+
+        // Hand-edited values are kept within bounds
+        config.num_ctx = Some(1);
+        assert_eq!(
+            options_request(&config)["options"]["num_ctx"],
+            config::MIN_NUM_CTX
+        );
+    }
+
+    #[test]
+    fn test_model_show_tolerates_missing_fields() {
+        let show: ShowResponse = serde_json::from_str(
+            r#"{"parameters":"stop \"<|eot_id|>\"","template":"{{ .Prompt }}",
+                "model_info":{"llama.context_length":131072}}"#,
+        )
+        .unwrap();
+        let show = ModelShow::from(show);
+
+        assert_eq!(show.parameters, "stop \"<|eot_id|>\"");
+        assert_eq!(show.template, "{{ .Prompt }}");
+        assert_eq!(show.context_length, Some(131072));
+        assert!(show.license.is_empty() && show.system.is_empty());
+        assert!(!show.supports_thinking());
+        assert!(!show.supports_vision());
+    }
+
+    #[test]
+    fn test_model_label_without_details() {
+        let model = AvailableModel {
+            name: "phi3:mini".to_string(),
+            display_size: "2.2 GB".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(model.label(), "phi3:mini (2.2 GB)");
+
+        let model = AvailableModel {
+            name: "gpt-4o".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(model.label(), "gpt-4o");
+    }
+
+    #[test]
+    fn test_auth_headers() {
+        assert!(auth_headers("").is_empty());
+        assert!(auth_headers("  ").is_empty());
+
+        let headers = auth_headers("secret");
+        let value = &headers[AUTHORIZATION];
+        assert_eq!(value, "Bearer secret");
+        assert!(value.is_sensitive());
+    }
 }
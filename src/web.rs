@@ -2,12 +2,151 @@
 
 //! Web search functionality for augmenting AI responses.
 //!
-//! Uses DuckDuckGo's instant answer API for quick searches.
+//! Uses DuckDuckGo's instant answer API by default, or a SearXNG instance.
 
-use serde::Deserialize;
+use crate::http;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 const SEARCH_URL: &str = "https://api.duckduckgo.com/";
 
+/// Number of distinct searches kept in the cache.
+const SEARCH_CACHE_SIZE: usize = 32;
+
+/// Results of recent searches, shared by all conversations.
+static SEARCH_CACHE: LazyLock<Mutex<SearchCache>> = LazyLock::new(Default::default);
+
+/// Prefix that forces a web search for one message.
+pub const SEARCH_COMMAND: &str = "/search";
+
+/// Keywords that suggest the user wants current/accurate info (triggers web search).
+const SEARCH_KEYWORDS: &[&str] = &[
+    // COSMIC/Pop specific
+    "cosmic",
+    "pop!_os",
+    "pop os",
+    "popos",
+    "system76",
+    "libcosmic",
+    "iced",
+    // Time-sensitive
+    "latest",
+    "new",
+    "recent",
+    "current",
+    "update",
+    "2024",
+    "2025",
+    "2026",
+    // Questions that benefit from search
+    "how do i",
+    "how to",
+    "what is",
+    "where is",
+    "why does",
+    "can i",
+    "does",
+    "is there",
+    "install",
+    "configure",
+    "setup",
+    "setting",
+    "config",
+    // Error/troubleshooting
+    "error",
+    "problem",
+    "issue",
+    "fix",
+    "broken",
+    "not working",
+    "failed",
+    "crash",
+];
+
+/// Service that web searches are sent to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchBackend {
+    /// DuckDuckGo's instant answer API.
+    #[default]
+    DuckDuckGo,
+    /// A SearXNG instance with the JSON format enabled.
+    SearXNG { base_url: String },
+}
+
+impl SearchBackend {
+    /// Backend names in settings order.
+    pub const LABELS: [&str; 2] = ["DuckDuckGo", "SearXNG"];
+
+    /// Position of this backend in `LABELS`.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::DuckDuckGo => 0,
+            Self::SearXNG { .. } => 1,
+        }
+    }
+}
+
+/// When to run a web search for a query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchMode {
+    /// Search when the query matches the keyword heuristic.
+    #[default]
+    Heuristic,
+    /// Always search.
+    Always,
+    /// Never search.
+    Never,
+}
+
+impl SearchMode {
+    pub const ALL: [Self; 3] = [Self::Heuristic, Self::Always, Self::Never];
+
+    /// Name shown in the settings.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Heuristic => "When the question suggests it",
+            Self::Always => "Always",
+            Self::Never => "Never",
+        }
+    }
+
+    /// The mode a per-message override switches to next.
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Heuristic => Self::Always,
+            Self::Always => Self::Never,
+            Self::Never => Self::Heuristic,
+        }
+    }
+}
+
+/// Whether a query would benefit from a web search.
+///
+/// This is the heuristic behind `SearchMode::Heuristic`: the query is
+/// searched when it contains, case-insensitively, any of the
+/// `SEARCH_KEYWORDS` — COSMIC and Pop!_OS names, time-sensitive words such
+/// as "latest", how-to phrasing and troubleshooting terms. Matching is by
+/// substring, so "installing" matches "install".
+pub fn should_search(query: &str) -> bool {
+    let query_lower = query.to_lowercase();
+    SEARCH_KEYWORDS
+        .iter()
+        .any(|keyword| query_lower.contains(keyword))
+}
+
+/// The query of a `/search <query>` message, which is searched regardless
+/// of the search mode.
+pub fn search_command(input: &str) -> Option<&str> {
+    let query = input
+        .trim_start()
+        .strip_prefix(SEARCH_COMMAND)?
+        .strip_prefix(char::is_whitespace)?
+        .trim();
+    (!query.is_empty()).then_some(query)
+}
+
 #[derive(Debug, Deserialize)]
 struct DdgResponse {
     #[serde(rename = "Abstract")]
@@ -26,7 +165,23 @@ struct RelatedTopic {
     text: Option<String>,
 }
 
-/// Search result from DuckDuckGo.
+#[derive(Debug, Deserialize)]
+struct SearxResponse {
+    #[serde(default)]
+    results: Vec<SearxResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxResult {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+/// Search result from a search backend.
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub summary: String,
@@ -35,9 +190,153 @@ pub struct SearchResult {
     pub related: Vec<String>,
 }
 
-/// Perform a web search using DuckDuckGo's instant answer API.
-pub async fn search(query: &str) -> Option<SearchResult> {
-    let client = reqwest::Client::new();
+/// Perform a web search with the given backend.
+///
+/// At most `related_limit` related topics are included in the result.
+/// `Ok(None)` means nothing useful was found; errors are only reported for
+/// misconfigured backends, so they can be fixed.
+///
+/// The same query (ignoring case and spacing) is answered from the cache
+/// for `ttl`; a zero `ttl` always searches.
+pub async fn search(
+    query: &str,
+    backend: &SearchBackend,
+    related_limit: usize,
+    ttl: Duration,
+) -> Result<Option<SearchResult>, String> {
+    let request = SearchRequest {
+        query,
+        backend,
+        related_limit,
+    };
+    cached_search(&SEARCH_CACHE, &HttpSearch, &request, ttl, Instant::now()).await
+}
+
+/// One search, as handed to a `SearchProvider`.
+struct SearchRequest<'a> {
+    query: &'a str,
+    backend: &'a SearchBackend,
+    related_limit: usize,
+}
+
+impl SearchRequest<'_> {
+    /// Cache key: the same search with different case or spacing matches.
+    fn cache_key(&self) -> String {
+        let query = self
+            .query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        format!("{:?}|{}|{}", self.backend, self.related_limit, query)
+    }
+}
+
+/// Runs searches for the cache; replaced by a fake in tests.
+trait SearchProvider {
+    async fn fetch(&self, request: &SearchRequest<'_>) -> Result<Option<SearchResult>, String>;
+}
+
+/// Searches over HTTP with the request's backend.
+struct HttpSearch;
+
+impl SearchProvider for HttpSearch {
+    async fn fetch(&self, request: &SearchRequest<'_>) -> Result<Option<SearchResult>, String> {
+        let (query, limit) = (request.query, request.related_limit);
+        match request.backend {
+            SearchBackend::DuckDuckGo => search_duckduckgo(query, limit).await,
+            SearchBackend::SearXNG { base_url } => search_searxng(base_url, query, limit).await,
+        }
+    }
+}
+
+/// A cached search and when it was fetched.
+struct CacheEntry {
+    key: String,
+    fetched: Instant,
+    result: Option<SearchResult>,
+}
+
+/// Recent search results, least recently used first.
+#[derive(Default)]
+struct SearchCache {
+    entries: VecDeque<CacheEntry>,
+}
+
+impl SearchCache {
+    /// The result cached for `key` if it is younger than `ttl`. Expired
+    /// entries are dropped along the way.
+    fn get(&mut self, key: &str, ttl: Duration, now: Instant) -> Option<Option<SearchResult>> {
+        self.entries
+            .retain(|entry| now.duration_since(entry.fetched) < ttl);
+        let index = self.entries.iter().position(|entry| entry.key == key)?;
+        let entry = self.entries.remove(index)?;
+        let result = entry.result.clone();
+        self.entries.push_back(entry);
+        Some(result)
+    }
+
+    /// Store a result, evicting the least recently used one when full.
+    fn insert(&mut self, key: String, result: Option<SearchResult>, now: Instant) {
+        self.entries.retain(|entry| entry.key != key);
+        if self.entries.len() >= SEARCH_CACHE_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(CacheEntry {
+            key,
+            fetched: now,
+            result,
+        });
+    }
+}
+
+/// Answer from `cache` when still fresh at `now`, else fetch and remember
+/// the result as fetched then.
+///
+/// Errors aren't cached, so a fixed backend works on the next try.
+async fn cached_search(
+    cache: &Mutex<SearchCache>,
+    provider: &impl SearchProvider,
+    request: &SearchRequest<'_>,
+    ttl: Duration,
+    now: Instant,
+) -> Result<Option<SearchResult>, String> {
+    let key = request.cache_key();
+    // Never hold the lock across the fetch
+    let cached = cache
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key, ttl, now);
+    if let Some(result) = cached {
+        return Ok(result);
+    }
+
+    let result = provider.fetch(request).await?;
+    if !ttl.is_zero() {
+        cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, result.clone(), now);
+    }
+    Ok(result)
+}
+
+/// Client for search requests, through the configured proxy.
+fn search_client() -> Result<reqwest::Client, String> {
+    http::client_builder()
+        .build()
+        .map_err(|e| format!("Could not set up web search: {}", e))
+}
+
+/// Search using DuckDuckGo's instant answer API.
+///
+/// Network failures count as nothing found; only a client that can't be
+/// set up is an error.
+async fn search_duckduckgo(
+    query: &str,
+    related_limit: usize,
+) -> Result<Option<SearchResult>, String> {
+    let client = search_client()?;
 
     let response = client
         .get(SEARCH_URL)
@@ -48,14 +347,17 @@ pub async fn search(query: &str) -> Option<SearchResult> {
             ("skip_disambig", "1"),
         ])
         .send()
-        .await
-        .ok()?;
-
-    let ddg: DdgResponse = response.json().await.ok()?;
+        .await;
+    let Ok(response) = response else {
+        return Ok(None);
+    };
+    let Ok(ddg) = response.json::<DdgResponse>().await else {
+        return Ok(None);
+    };
 
     // If we have an abstract, use it
     if !ddg.abstract_text.is_empty() {
-        return Some(SearchResult {
+        return Ok(Some(SearchResult {
             summary: ddg.abstract_text,
             source: ddg.abstract_source,
             url: ddg.abstract_url,
@@ -63,9 +365,9 @@ pub async fn search(query: &str) -> Option<SearchResult> {
                 .related_topics
                 .iter()
                 .filter_map(|t| t.text.clone())
-                .take(3)
+                .take(related_limit)
                 .collect(),
-        });
+        }));
     }
 
     // Otherwise try to get info from related topics
@@ -73,19 +375,73 @@ pub async fn search(query: &str) -> Option<SearchResult> {
         .related_topics
         .iter()
         .filter_map(|t| t.text.clone())
-        .take(5)
+        .take(related_limit)
         .collect();
 
     if !related.is_empty() {
-        return Some(SearchResult {
+        return Ok(Some(SearchResult {
             summary: related.join("\n\n"),
             source: "DuckDuckGo".to_string(),
             url: format!("https://duckduckgo.com/?q={}", urlencoding::encode(query)),
             related: vec![],
-        });
+        }));
     }
 
-    None
+    Ok(None)
+}
+
+/// Search using a SearXNG instance's JSON API.
+///
+/// The top result becomes the summary and the following ones the related
+/// info.
+async fn search_searxng(
+    base_url: &str,
+    query: &str,
+    related_limit: usize,
+) -> Result<Option<SearchResult>, String> {
+    if base_url.trim().is_empty() {
+        return Err("Set a SearXNG URL in the settings to search the web".to_string());
+    }
+    let search_url = format!("{}/search", base_url.trim().trim_end_matches('/'));
+    let client = search_client()?;
+
+    let response = client
+        .get(&search_url)
+        .query(&[("q", query), ("format", "json")])
+        .send()
+        .await
+        .map_err(|e| format!("SearXNG connection error: {}", e))?;
+
+    // Instances answer 403 when the JSON format isn't enabled
+    if response.status() == reqwest::StatusCode::FORBIDDEN {
+        return Err(format!(
+            "SearXNG at {} doesn't allow JSON results; add json to search.formats in its settings.yml",
+            base_url
+        ));
+    }
+    if !response.status().is_success() {
+        return Err(format!("SearXNG error: {}", response.status()));
+    }
+
+    let searx: SearxResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("SearXNG returned unexpected data: {}", e))?;
+
+    let mut results = searx.results.into_iter().filter(|r| !r.content.is_empty());
+    let Some(top) = results.next() else {
+        return Ok(None);
+    };
+
+    Ok(Some(SearchResult {
+        summary: top.content,
+        source: top.title,
+        url: top.url,
+        related: results
+            .take(related_limit)
+            .map(|r| format!("{}: {} ({})", r.title, r.content, r.url))
+            .collect(),
+    }))
 }
 
 /// Format search results for inclusion in context.
@@ -105,3 +461,79 @@ pub fn format_results(result: &SearchResult) -> String {
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts fetches instead of hitting the network.
+    #[derive(Default)]
+    struct CountingSearch {
+        calls: AtomicUsize,
+    }
+
+    impl SearchProvider for CountingSearch {
+        async fn fetch(&self, request: &SearchRequest<'_>) -> Result<Option<SearchResult>, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(SearchResult {
+                summary: request.query.to_string(),
+                source: String::new(),
+                url: String::new(),
+                related: Vec::new(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_cache_reuses_and_expires() {
+        let cache = Mutex::new(SearchCache::default());
+        let provider = CountingSearch::default();
+        let ttl = Duration::from_secs(60);
+        let request = |query| SearchRequest {
+            query,
+            backend: &SearchBackend::DuckDuckGo,
+            related_limit: 5,
+        };
+
+        let now = Instant::now();
+
+        cached_search(&cache, &provider, &request("COSMIC  panel"), ttl, now)
+            .await
+            .unwrap();
+        let cached = cached_search(&cache, &provider, &request("cosmic panel"), ttl, now)
+            .await
+            .unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cached.unwrap().summary, "COSMIC  panel");
+
+        // Past the TTL
+        let later = now + Duration::from_secs(61);
+        cached_search(&cache, &provider, &request("cosmic panel"), ttl, later)
+            .await
+            .unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_should_search_keywords() {
+        assert!(should_search("How do I change the COSMIC wallpaper?"));
+        assert!(should_search("wifi keeps failing after the LATEST update"));
+        assert!(should_search("installing steam"));
+        assert!(!should_search("Tell me a joke"));
+        assert!(!should_search("Summarize this paragraph"));
+    }
+
+    #[test]
+    fn test_search_command() {
+        assert_eq!(
+            search_command("/search rust 2024 edition"),
+            Some("rust 2024 edition")
+        );
+        assert_eq!(search_command("  /search   tokio  "), Some("tokio"));
+        assert_eq!(search_command("/search"), None);
+        assert_eq!(search_command("/search   "), None);
+        assert_eq!(search_command("/searching things"), None);
+        assert_eq!(search_command("please /search this"), None);
+    }
+}